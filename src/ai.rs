@@ -0,0 +1,274 @@
+//! A computer-controlled opponent that can play either paddle.
+//!
+//! The AI only sees the ball as it was `reaction_delay` seconds ago, misjudges where it is by up
+//! to `prediction_error` pixels, and moves at no more than `max_speed` of the paddle's full speed.
+//! With `aim_intent`, it hits the ball off-center to angle it away from the other player.
+//! Better AIs work out where the ball will bounce to, while the easiest just chase it.
+
+use paddle::KEYBOARD_SPEED;
+use rand::Rng;
+use std::collections::VecDeque;
+use {GameRng, PaddleInput, Side, Simulation};
+
+// The furthest from the paddle's center that the AI aims to hit the ball, as a fraction of
+// the paddle's height. Any further and it risks missing altogether.
+const MAX_AIM_OFFSET: f32 = 0.4;
+
+/// The difficulty presets for the AI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    /// Parses a difficulty from its lowercase name.
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "medium" => Some(Difficulty::Medium),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    pub fn settings(self) -> AiSettings {
+        match self {
+            Difficulty::Easy => AiSettings {
+                reaction_delay: 0.3,
+                max_speed: 0.5,
+                prediction_error: 60.0,
+                aim_intent: 0.0,
+                predicts_bounces: false,
+            },
+            Difficulty::Medium => AiSettings {
+                reaction_delay: 0.15,
+                max_speed: 0.75,
+                prediction_error: 30.0,
+                aim_intent: 0.4,
+                predicts_bounces: true,
+            },
+            Difficulty::Hard => AiSettings {
+                reaction_delay: 0.05,
+                max_speed: 1.0,
+                prediction_error: 8.0,
+                aim_intent: 0.9,
+                predicts_bounces: true,
+            },
+        }
+    }
+}
+
+/// How well the AI plays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AiSettings {
+    /// How many seconds old the AI's view of the ball is.
+    pub reaction_delay: f32,
+    /// The fastest the AI moves its paddle, as a fraction of full speed.
+    pub max_speed: f32,
+    /// The most that the AI's idea of where to meet the ball can be off by, in pixels.
+    pub prediction_error: f32,
+    /// How much the AI tries to angle its returns away from the other player, from 0 to 1.
+    pub aim_intent: f32,
+    /// Whether the AI predicts where the ball will meet its paddle, rather than chasing it.
+    pub predicts_bounces: bool,
+}
+
+/// Where the ball was at some point in the past, and where it looked to be heading.
+#[derive(Clone, Copy, Debug)]
+struct Sighting {
+    time: f32,
+    center_y: f32,
+    vx: f32,
+    intercept: Option<f32>,
+}
+
+/// Plays one paddle, producing its input each tick.
+#[derive(Clone, Debug)]
+pub struct AiController {
+    side: Side,
+    settings: AiSettings,
+    rng: GameRng,
+
+    // The time since the AI started playing, and what it has seen of the ball since then
+    // that it hasn't reacted to yet.
+    elapsed: f32,
+    sightings: VecDeque<Sighting>,
+
+    // Whether the ball is heading for the AI's paddle, and where on the paddle to meet it.
+    incoming: bool,
+    offset: f32,
+}
+
+impl AiController {
+    pub fn new(side: Side, settings: AiSettings) -> AiController {
+        AiController::with_rng(side, settings, GameRng::from_entropy())
+    }
+
+    /// Creates an AI whose mistakes are picked by the given random number generator.
+    pub fn with_rng(side: Side, settings: AiSettings, rng: GameRng) -> AiController {
+        AiController {
+            side,
+            settings,
+            rng,
+
+            elapsed: 0.0,
+            sightings: VecDeque::new(),
+
+            incoming: false,
+            offset: 0.0,
+        }
+    }
+
+    /// The side of the court that the AI plays.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Looks at the match and decides how to move for the next tick of `dt` seconds.
+    pub fn update(&mut self, simulation: &Simulation, dt: f32) -> PaddleInput {
+        self.elapsed += dt;
+        let ball = simulation.ball().bounds();
+        self.sightings.push_back(Sighting {
+            time: self.elapsed,
+            center_y: ball.y + ball.height / 2.0,
+            vx: simulation.ball().velocity().0,
+            intercept: if self.settings.predicts_bounces { simulation.predict_intercept(self.side, dt) } else { None },
+        });
+
+        // React to the latest sighting that is at least `reaction_delay` old.
+        let react_time = self.elapsed - self.settings.reaction_delay;
+        while self.sightings.len() > 1 && self.sightings[1].time <= react_time {
+            self.sightings.pop_front();
+        }
+        let seen = self.sightings[0];
+
+        // Plan each return as the ball turns towards the paddle.
+        let incoming = match self.side {
+            Side::Left => seen.vx < 0.0,
+            Side::Right => seen.vx > 0.0,
+        };
+        if incoming && !self.incoming {
+            self.offset = self.plan_return(simulation);
+        }
+        self.incoming = incoming;
+
+        // Meet the ball, or wait in the middle for it to come back.
+        let target_y = if incoming {
+            seen.intercept.unwrap_or(seen.center_y) + self.offset
+        } else {
+            simulation.height() / 2.0
+        };
+        self.move_towards(simulation, target_y, dt)
+    }
+
+    /// Picks how far from the ball the paddle's center should be when they meet.
+    fn plan_return(&mut self, simulation: &Simulation) -> f32 {
+        let max_error = self.settings.prediction_error;
+        let error = if max_error > 0.0 { self.rng.gen_range(-max_error, max_error) } else { 0.0 };
+
+        // Hitting the ball below the paddle's center sends it down the screen, and above sends
+        // it up, so send it to the other half of the court from the opponent.
+        let opponent = simulation.paddle(self.side.opposite()).bounds;
+        let direction = if opponent.y + opponent.height / 2.0 < simulation.height() / 2.0 { 1.0 } else { -1.0 };
+        let paddle_height = simulation.paddle(self.side).bounds.height;
+        let aim = self.settings.aim_intent.clamp(0.0, 1.0) * MAX_AIM_OFFSET * paddle_height;
+        error - direction * aim
+    }
+
+    /// Moves the paddle's center towards `target_y`, without going over the speed limit.
+    fn move_towards(&self, simulation: &Simulation, target_y: f32, dt: f32) -> PaddleInput {
+        let paddle = simulation.paddle(self.side).bounds;
+        let distance = target_y - (paddle.y + paddle.height / 2.0);
+        let max_speed = self.settings.max_speed.clamp(0.0, 1.0);
+        let axis = if dt > 0.0 { (distance / (KEYBOARD_SPEED * dt)).clamp(-max_speed, max_speed) } else { 0.0 };
+        PaddleInput { axis, ..PaddleInput::default() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Input, GAME_HEIGHT, GAME_WIDTH};
+
+    const PERFECT: AiSettings = AiSettings {
+        reaction_delay: 0.0,
+        max_speed: 1.0,
+        prediction_error: 0.0,
+        aim_intent: 0.0,
+        predicts_bounces: false,
+    };
+
+    fn simulation() -> Simulation {
+        Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, 1)
+    }
+
+    #[test]
+    fn parses_difficulties() {
+        assert_eq!(Difficulty::from_name("hard"), Some(Difficulty::Hard));
+        assert_eq!(Difficulty::from_name("impossible"), None);
+        let (easy, hard) = (Difficulty::Easy.settings(), Difficulty::Hard.settings());
+        assert!(easy.reaction_delay > hard.reaction_delay);
+        assert!(easy.max_speed < hard.max_speed);
+        assert!(easy.prediction_error > hard.prediction_error);
+    }
+
+    #[test]
+    fn returns_the_serve() {
+        let mut sim = simulation();
+        let side = sim.ball().receiver();
+        let mut ai = AiController::new(side, PERFECT);
+        let params = sim.update_params(1.0 / 60.0);
+        let direction = sim.ball().velocity().0.signum();
+        for _ in 0..60 * 10 {
+            let mut input = Input::default();
+            *input.paddle_mut(side) = ai.update(&sim, params.dt);
+            sim.step(&params, &input);
+            if sim.ball().velocity().0.signum() != direction {
+                break;
+            }
+        }
+        assert_eq!(sim.paddle(side.opposite()).score, 0);
+        assert!(sim.ball().velocity().0.signum() != direction, "the ball was never returned");
+    }
+
+    #[test]
+    fn heads_for_predicted_intercept() {
+        let sim = simulation();
+        let side = sim.ball().receiver();
+        let mut ai = AiController::new(side, AiSettings { predicts_bounces: true, ..PERFECT });
+
+        // Over a whole second, the paddle could reach anywhere, so it aims right for the intercept.
+        let input = ai.update(&sim, 1.0);
+        let paddle = sim.paddle(side).bounds;
+        let target = paddle.y + paddle.height / 2.0 + input.axis * KEYBOARD_SPEED;
+        let intercept = sim.predict_intercept(side, 1.0).unwrap();
+        assert!((target - intercept).abs() < 0.01);
+    }
+
+    #[test]
+    fn speed_is_limited() {
+        // The ball is heading away, so the AI heads for the middle of the court from the top.
+        let mut sim = simulation();
+        let side = sim.ball().receiver().opposite();
+        sim.paddle_mut(side).bounds.y = 0.0;
+        let mut ai = AiController::new(side, AiSettings { max_speed: 0.5, ..PERFECT });
+        let input = ai.update(&sim, 0.1);
+        assert_eq!(input.axis, 0.5);
+    }
+
+    #[test]
+    fn aims_away_from_opponent() {
+        let mut sim = simulation();
+        let side = sim.ball().receiver();
+        sim.paddle_mut(side.opposite()).bounds.y = 0.0;
+        let mut ai = AiController::new(side, AiSettings { aim_intent: 1.0, ..PERFECT });
+        ai.update(&sim, 0.1);
+
+        // With the opponent at the top, the ball should hit below the paddle's center.
+        let ball = sim.ball().bounds();
+        let target = ball.y + ball.height / 2.0 + ai.offset;
+        assert!(target < ball.y);
+    }
+}
@@ -1,83 +1,719 @@
 //! Represents the ball in a game of Pong.
 
-use {Game, Paddle, Rectangle, Result, UpdateParams};
-use glium::Frame;
+use {GameRng, Paddle, Rectangle, Side, SweepHit, UpdateParams};
+use rand::Rng;
+use state;
+use std::f32::consts::PI;
 
 const WIDTH: f32 = 15.0;
 const HEIGHT: f32 = 15.0;
 const BALL_BOUNCE_SPEEDUP: f32 = 1.15;
 const BALL_STARTING_SPEED: f32 = 500.0;
+const BALL_MAX_BOUNCE_ANGLE: f32 = PI * 5.0 / 18.0;
+
+// Spin curves the ball by rotating its velocity, measured in radians per second.
+const BALL_MAX_SPIN: f32 = 1.5;
+// Fraction of the paddle's vertical speed (pixels per second) that becomes spin on a hit.
+const PADDLE_SPIN_TRANSFER: f32 = 0.002;
+// Fraction of the paddle's vertical velocity that is added to the ball on a hit.
+const PADDLE_VELOCITY_TRANSFER: f32 = 0.25;
+// Spin lost per second while the ball is in the air.
+const BALL_SPIN_FRICTION: f32 = 0.8;
+// Fraction of spin kept after the ball scrapes along a wall.
+const BALL_WALL_SPIN_FRICTION: f32 = 0.5;
+
+// Upper limit on the number of collisions resolved within a single update.
+const MAX_COLLISIONS_PER_UPDATE: u32 = 8;
+
+// How far ahead in seconds the ball's path is predicted before giving up.
+const MAX_PREDICTION_TIME: f32 = 10.0;
+
+/// Controls how the ball is put back into play after a point.
+#[derive(Clone, Copy, Debug)]
+pub struct ServeSettings {
+    /// The maximum angle in radians away from horizontal that the ball may be served at.
+    pub max_angle: f32,
+    /// The time in seconds that the ball waits before launching.
+    pub delay: f32,
+    pub direction: ServeDirection,
+}
+
+impl Default for ServeSettings {
+    fn default() -> ServeSettings {
+        ServeSettings {
+            max_angle: PI / 6.0,
+            delay: 3.0,
+            direction: ServeDirection::TowardConceder,
+        }
+    }
+}
+
+/// Which player the ball is served towards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServeDirection {
+    /// Serve towards the player who just lost the point.
+    TowardConceder,
+    /// Serve towards each player in turn.
+    Alternate,
+}
 
 // The ball has a speed and moves once per frame.
 #[derive(Clone, Debug)]
 pub struct Ball {
     bounds: Rectangle,
+    previous_bounds: Rectangle,
     vx: f32,
     vy: f32,
+    spin: f32,
     start_timer: f32,
+    serve_settings: ServeSettings,
+    last_serve: Side,
+    rng: GameRng,
 }
 
 impl Ball {
     // Creates a new ball at the given position.
     pub fn new(x: f32, y: f32) -> Ball {
+        Ball::with_serve_settings(x, y, ServeSettings::default())
+    }
+
+    /// Creates a new ball at the given position that serves using the given settings.
+    /// The first serve goes to a random player.
+    pub fn with_serve_settings(x: f32, y: f32, serve_settings: ServeSettings) -> Ball {
+        Ball::with_rng(x, y, serve_settings, GameRng::from_entropy())
+    }
+
+    /// Creates a new ball that picks its serves using the given random number generator,
+    /// so that a seeded generator always gives the same serves.
+    pub fn with_rng(x: f32, y: f32, serve_settings: ServeSettings, mut rng: GameRng) -> Ball {
+        let first_serve = if rng.gen() { Side::Left } else { Side::Right };
+        let bounds = Rectangle::new_centered(x, y, WIDTH, HEIGHT);
         let mut ball = Ball {
             vx: 0.0,
             vy: 0.0,
+            spin: 0.0,
             start_timer: 0.0,
-            bounds: Rectangle {
-                x: 0.0,
-                y: 0.0,
-                width: WIDTH,
-                height: HEIGHT,
-            },
+            bounds,
+            previous_bounds: bounds,
+            serve_settings,
+            last_serve: first_serve.opposite(),
+            rng,
         };
-        ball.reset(x, y);
+        ball.reset(x, y, first_serve);
         ball
     }
 
+    /// The position of the ball after the last update.
+    pub fn bounds(&self) -> Rectangle {
+        self.bounds
+    }
+
+    /// The position of the ball before the last update.
+    pub fn previous_bounds(&self) -> Rectangle {
+        self.previous_bounds
+    }
+
+    /// The velocity of the ball in pixels per second.
+    pub fn velocity(&self) -> (f32, f32) {
+        (self.vx, self.vy)
+    }
+
+    /// The number of seconds remaining before the ball is launched.
+    pub fn start_timer(&self) -> f32 {
+        self.start_timer
+    }
+
+    /// The player the ball is being served towards, or was last served towards.
+    pub fn receiver(&self) -> Side {
+        self.last_serve
+    }
+
+    /// Ends the countdown so that the ball starts moving on the next update.
+    pub fn serve_now(&mut self) {
+        self.start_timer = 0.0;
+    }
+
+    /// Writes everything about the ball that affects how the match plays out, bit for bit,
+    /// including the state of its random number generator. The serve settings are left out,
+    /// since they are fixed when the ball is created.
+    pub fn write_state(&self, out: &mut Vec<u8>) {
+        self.bounds.write_state(out);
+        self.previous_bounds.write_state(out);
+        for &value in &[self.vx, self.vy, self.spin, self.start_timer] {
+            state::write_f32(out, value);
+        }
+        state::write_u8(out, self.last_serve as u8);
+        for &word in &self.rng.state() {
+            state::write_u32(out, word);
+        }
+    }
+
+    /// Restores the state written by `write_state`.
+    pub fn read_state(&mut self, input: &mut &[u8]) -> Option<()> {
+        self.bounds = Rectangle::read_state(input)?;
+        self.previous_bounds = Rectangle::read_state(input)?;
+        self.vx = state::read_f32(input)?;
+        self.vy = state::read_f32(input)?;
+        self.spin = state::read_f32(input)?;
+        self.start_timer = state::read_f32(input)?;
+        self.last_serve = match state::read_u8(input)? {
+            0 => Side::Left,
+            1 => Side::Right,
+            _ => return None,
+        };
+        let mut rng = [0; 4];
+        for word in &mut rng {
+            *word = state::read_u32(input)?;
+        }
+        self.rng = GameRng::from_state(rng);
+        Some(())
+    }
+
+    /// Predicts the height of the ball's center when its leading edge reaches `x`.
+    /// The flight is simulated one update at a time with spin and bounces off the walls,
+    /// but ignoring the paddles and any countdown before the serve.
+    /// Returns `None` if the ball is heading away from `x` or would take too long to get there.
+    pub fn predict_y_at(&self, params: &UpdateParams, x: f32) -> Option<f32> {
+        if params.dt <= 0.0 {
+            return None;
+        }
+        let mut ball = self.clone();
+        for _ in 0..(MAX_PREDICTION_TIME / params.dt) as u32 {
+            // How far the leading edge still has to go.
+            let remaining = if ball.vx > 0.0 {
+                x - (ball.bounds.x + ball.bounds.width)
+            } else if ball.vx < 0.0 {
+                ball.bounds.x - x
+            } else {
+                return None;
+            };
+            if remaining < 0.0 {
+                return None;
+            }
+
+            // Finish with the part of an update that it takes to reach `x`.
+            let step = ball.vx.abs() * params.dt;
+            if remaining <= step {
+                let y = ball.bounds.y + ball.vy * params.dt * remaining / step;
+                let y = y.clamp(0.0, params.game_height - ball.bounds.height);
+                return Some(y + ball.bounds.height / 2.0);
+            }
+
+            ball.apply_spin(params.dt);
+            ball.bounds.x += ball.vx * params.dt;
+            ball.bounds.y += ball.vy * params.dt;
+            ball.check_wall_collision(params);
+        }
+        None
+    }
+
     /// Resets the ball back to the given position.
+    /// `conceder` is the player who lost the last point.
     /// The ball will stay in place for a moment before moving.
-    fn reset(&mut self, x: f32, y: f32) {
+    fn reset(&mut self, x: f32, y: f32, conceder: Side) {
         self.bounds.x = x - self.bounds.width / 2.0;
         self.bounds.y = y - self.bounds.height / 2.0;
-        
-        // TODO: Give the ball a random velocity.
+        // Don't draw the ball sliding back to the center.
+        self.previous_bounds = self.bounds;
+        self.serve(conceder);
+    }
+
+    /// Gives the ball a random velocity within the serve cone and starts the countdown.
+    fn serve(&mut self, conceder: Side) {
+        let target = match self.serve_settings.direction {
+            ServeDirection::TowardConceder => conceder,
+            ServeDirection::Alternate => self.last_serve.opposite(),
+        };
+        self.last_serve = target;
+
+        let max_angle = self.serve_settings.max_angle.abs();
+        let angle = if max_angle > 0.0 { self.rng.gen_range(-max_angle, max_angle) } else { 0.0 };
+        let direction = match target {
+            Side::Left => -1.0,
+            Side::Right => 1.0,
+        };
+        self.vx = direction * BALL_STARTING_SPEED * angle.cos();
+        self.vy = BALL_STARTING_SPEED * angle.sin();
+        self.spin = 0.0;
+        self.start_timer = self.serve_settings.delay;
     }
 
     /// Updates the position of the ball and checks for collisions.
     pub fn update(&mut self, params: &UpdateParams, left_paddle: &mut Paddle, right_paddle: &mut Paddle) {
+        self.previous_bounds = self.bounds;
+
         // The ball stays still until a timer elapses.
         if self.start_timer > 0.0 {
             self.start_timer -= params.dt;
         } else {
-            self.bounds.x += self.vx * params.dt;
-            self.bounds.y += self.vy * params.dt;
+            self.apply_spin(params.dt);
+            self.travel(params, left_paddle, right_paddle);
         }
 
-        // Check collision.
+        // Catch any overlap that the sweep can't see, such as a paddle moving onto the ball.
         self.check_paddle_collision(left_paddle);
         self.check_paddle_collision(right_paddle);
         self.check_wall_collision(params);
         self.check_goal(params, left_paddle, right_paddle);
     }
 
-    /// Draws the paddle on the screen.
-    pub fn render(&self, game: &Game, frame: &mut Frame) -> Result<()> {
-        game.draw_rectangle(frame, self.bounds, [1.0, 1.0, 1.0, 1.0])
+    /// Moves the ball along its velocity for one update, bouncing off anything in the way.
+    /// Each collision is resolved at the moment of contact and the ball continues on for
+    /// the rest of the update, so a fast ball can't skip over a paddle between frames.
+    fn travel(&mut self, params: &UpdateParams, left_paddle: &Paddle, right_paddle: &Paddle) {
+        // The walls extend well past the sides of the screen so that they are always in the way.
+        let top_wall = Rectangle::new(-params.game_width, -params.game_height, params.game_width * 3.0, params.game_height);
+        let bottom_wall = Rectangle::new(-params.game_width, params.game_height, params.game_width * 3.0, params.game_height);
+
+        let mut remaining = params.dt;
+        for _ in 0..MAX_COLLISIONS_PER_UPDATE {
+            let dx = self.vx * remaining;
+            let dy = self.vy * remaining;
+
+            // Find the first thing the ball would hit.
+            let mut first_hit: Option<(SweepHit, Option<&Paddle>)> = None;
+            let obstacles = [
+                (top_wall, None),
+                (bottom_wall, None),
+                (left_paddle.bounds, Some(left_paddle)),
+                (right_paddle.bounds, Some(right_paddle)),
+            ];
+            for &(bounds, paddle) in obstacles.iter() {
+                if let Some(hit) = self.bounds.sweep(dx, dy, bounds) {
+                    if first_hit.map_or(true, |(first, _)| hit.time < first.time) {
+                        first_hit = Some((hit, paddle));
+                    }
+                }
+            }
+
+            let (hit, paddle) = match first_hit {
+                Some(first_hit) => first_hit,
+                None => {
+                    self.bounds.x += dx;
+                    self.bounds.y += dy;
+                    return;
+                }
+            };
+
+            // Move up to the point of contact and bounce.
+            self.bounds.x += dx * hit.time;
+            self.bounds.y += dy * hit.time;
+            remaining *= 1.0 - hit.time;
+            match paddle {
+                Some(paddle) if hit.normal_x != 0.0 => self.bounce_off_paddle(paddle, hit.normal_x),
+                Some(_) => self.vy = -self.vy,
+                None => {
+                    self.vy = -self.vy;
+                    self.spin *= BALL_WALL_SPIN_FRICTION;
+                }
+            }
+        }
+    }
+
+    /// Curves the ball's path by rotating its velocity according to its spin.
+    /// The rotation keeps the ball's speed the same, and the spin slowly wears off.
+    fn apply_spin(&mut self, dt: f32) {
+        let (sin, cos) = (self.spin * dt).sin_cos();
+        let (vx, vy) = (self.vx, self.vy);
+        self.vx = vx * cos - vy * sin;
+        self.vy = vx * sin + vy * cos;
+        self.spin *= (-BALL_SPIN_FRICTION * dt).exp();
     }
 
     /// Handles collision between the ball and a paddle.
     fn check_paddle_collision(&mut self, paddle: &Paddle) {
-        // TODO: Check collision against the given paddle, and reflect the ball if it's colliding.
+        if !self.bounds.intersects(paddle.bounds) {
+            return;
+        }
+
+        // Only reflect the ball if it is travelling towards the paddle.
+        // Otherwise the ball could get stuck inside the paddle, bouncing back and forth.
+        let ball_center = self.bounds.x + self.bounds.width / 2.0;
+        let paddle_center = paddle.bounds.x + paddle.bounds.width / 2.0;
+        let direction = if ball_center >= paddle_center && self.vx < 0.0 {
+            // Hit the right side of the paddle.
+            self.bounds.x = paddle.bounds.x + paddle.bounds.width;
+            1.0
+        } else if ball_center < paddle_center && self.vx > 0.0 {
+            // Hit the left side of the paddle.
+            self.bounds.x = paddle.bounds.x - self.bounds.width;
+            -1.0
+        } else {
+            return;
+        };
+
+        self.bounce_off_paddle(paddle, direction);
+    }
+
+    /// Sends the ball back off the face of the paddle.
+    /// `direction` is `1.0` if the ball hit the right face of the paddle and `-1.0` for the left.
+    fn bounce_off_paddle(&mut self, paddle: &Paddle, direction: f32) {
+        // The ball leaves at an angle depending on where it hit the paddle,
+        // and speeds up with every hit.
+        let angle = self.deflection_angle(paddle);
+        let speed = (self.vx * self.vx + self.vy * self.vy).sqrt() * BALL_BOUNCE_SPEEDUP;
+
+        // A moving paddle drags the ball along with it, which changes the angle but not the speed.
+        let vy = speed * angle.sin() + paddle.vy * PADDLE_VELOCITY_TRANSFER;
+        let angle = vy.atan2(speed * angle.cos()).clamp(-BALL_MAX_BOUNCE_ANGLE, BALL_MAX_BOUNCE_ANGLE);
+        self.vx = direction * speed * angle.cos();
+        self.vy = speed * angle.sin();
+
+        // The paddle also puts spin on the ball, curving it in the direction the paddle moved.
+        let spin = self.spin + direction * paddle.vy * PADDLE_SPIN_TRANSFER;
+        self.spin = spin.clamp(-BALL_MAX_SPIN, BALL_MAX_SPIN);
+    }
+
+    /// Calculates the outgoing angle from horizontal after the ball hits the given paddle.
+    /// A hit at the center of the paddle returns the ball straight back, while hits
+    /// towards the top or bottom edge send it off at up to `BALL_MAX_BOUNCE_ANGLE`.
+    /// Positive angles point down the screen.
+    fn deflection_angle(&self, paddle: &Paddle) -> f32 {
+        let ball_center = self.bounds.y + self.bounds.height / 2.0;
+        let paddle_center = paddle.bounds.y + paddle.bounds.height / 2.0;
+
+        // The ball can touch the paddle anywhere along its height plus the ball's own height,
+        // so normalize the offset to the range [-1, 1] over that reach.
+        let reach = (paddle.bounds.height + self.bounds.height) / 2.0;
+        let offset = ((ball_center - paddle_center) / reach).clamp(-1.0, 1.0);
+        offset * BALL_MAX_BOUNCE_ANGLE
     }
 
     /// Handles collision between the ball and the top or bottom of the screen.
     fn check_wall_collision(&mut self, params: &UpdateParams) {
-        // TODO: Check collision against the top and bottom of the screen, and reflect the ball if it's colliding.
+        if self.bounds.y < 0.0 {
+            self.bounds.y = 0.0;
+            self.vy = self.vy.abs();
+            self.spin *= BALL_WALL_SPIN_FRICTION;
+        } else if self.bounds.y + self.bounds.height > params.game_height {
+            self.bounds.y = params.game_height - self.bounds.height;
+            self.vy = -self.vy.abs();
+            self.spin *= BALL_WALL_SPIN_FRICTION;
+        }
     }
 
     /// Handles collision between the ball and the left or right edge of the screen.
     fn check_goal(&mut self, params: &UpdateParams, left_paddle: &mut Paddle, right_paddle: &mut Paddle) {
-        // TODO: Check collision against the left or right edge, and give the appropriate player a point.
+        let conceder = if self.bounds.x + self.bounds.width < 0.0 {
+            // The ball went past the left paddle.
+            right_paddle.score += 1;
+            Side::Left
+        } else if self.bounds.x > params.game_width {
+            // The ball went past the right paddle.
+            left_paddle.score += 1;
+            Side::Right
+        } else {
+            return;
+        };
+
+        self.reset(params.game_width / 2.0, params.game_height / 2.0, conceder);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> UpdateParams {
+        UpdateParams {
+            dt: 1.0 / 60.0,
+            game_width: 1280.0,
+            game_height: 720.0,
+        }
+    }
+
+    fn ball_at(x: f32, y: f32, vx: f32, vy: f32) -> Ball {
+        let mut ball = Ball::new(x, y);
+        ball.vx = vx;
+        ball.vy = vy;
+        ball.start_timer = 0.0;
+        ball
+    }
+
+    #[test]
+    fn bounces_off_top_wall() {
+        let mut ball = ball_at(640.0, 5.0, 0.0, -300.0);
+        ball.bounds.y = -2.0;
+        ball.check_wall_collision(&params());
+        assert_eq!(ball.bounds.y, 0.0);
+        assert_eq!(ball.vy, 300.0);
+    }
+
+    #[test]
+    fn bounces_off_bottom_wall() {
+        let mut ball = ball_at(640.0, 715.0, 0.0, 300.0);
+        ball.check_wall_collision(&params());
+        assert_eq!(ball.bounds.y + ball.bounds.height, 720.0);
+        assert_eq!(ball.vy, -300.0);
+    }
+
+    fn speed(ball: &Ball) -> f32 {
+        (ball.vx * ball.vx + ball.vy * ball.vy).sqrt()
+    }
+
+    #[test]
+    fn reflects_off_left_paddle() {
+        let paddle = Paddle::new(25.0, 360.0);
+        let mut ball = ball_at(35.0, 360.0, -400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        assert_eq!(ball.bounds.x, paddle.bounds.x + paddle.bounds.width);
+        assert_eq!(ball.vx, 400.0 * BALL_BOUNCE_SPEEDUP);
+        assert_eq!(ball.vy, 0.0);
+    }
+
+    #[test]
+    fn center_hit_returns_straight_and_keeps_speed() {
+        let paddle = Paddle::new(25.0, 360.0);
+        let mut ball = ball_at(35.0, 360.0, -300.0, 400.0);
+        ball.check_paddle_collision(&paddle);
+        assert!((ball.vx - 500.0 * BALL_BOUNCE_SPEEDUP).abs() < 0.01);
+        assert!(ball.vy.abs() < 0.01);
+    }
+
+    #[test]
+    fn edge_hit_deflects_at_max_angle() {
+        // The ball's center is level with the bottom edge of the paddle plus half the ball height.
+        let paddle = Paddle::new(25.0, 360.0);
+        let edge = paddle.bounds.y + paddle.bounds.height + HEIGHT / 2.0;
+        let mut ball = ball_at(35.0, edge, -400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        let angle = ball.vy.atan2(ball.vx);
+        assert!((angle - BALL_MAX_BOUNCE_ANGLE).abs() < 0.001);
+        assert!((speed(&ball) - 400.0 * BALL_BOUNCE_SPEEDUP).abs() < 0.01);
+    }
+
+    #[test]
+    fn upper_half_hit_deflects_upward() {
+        let paddle = Paddle::new(1255.0, 360.0);
+        let mut ball = ball_at(1245.0, 335.0, 400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        let angle = ball.vy.atan2(-ball.vx);
+        assert!(ball.vx < 0.0);
+        assert!(angle < 0.0 && angle > -BALL_MAX_BOUNCE_ANGLE);
+        assert!((speed(&ball) - 400.0 * BALL_BOUNCE_SPEEDUP).abs() < 0.01);
+    }
+
+    #[test]
+    fn corner_hit_is_clamped_to_max_angle() {
+        // Overlapping only the top-right corner of the paddle.
+        let paddle = Paddle::new(25.0, 360.0);
+        let mut ball = ball_at(0.0, 0.0, -400.0, 200.0);
+        ball.bounds.x = paddle.bounds.x + paddle.bounds.width - 1.0;
+        ball.bounds.y = paddle.bounds.y - HEIGHT;
+        ball.check_paddle_collision(&paddle);
+        let angle = ball.vy.atan2(ball.vx);
+        assert!((angle + BALL_MAX_BOUNCE_ANGLE).abs() < 0.001);
+        assert!((speed(&ball) - 200.0 * 5f32.sqrt() * BALL_BOUNCE_SPEEDUP).abs() < 0.01);
+    }
+
+    #[test]
+    fn reflects_off_right_paddle() {
+        let paddle = Paddle::new(1255.0, 360.0);
+        let mut ball = ball_at(1245.0, 360.0, 400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        assert_eq!(ball.bounds.x + ball.bounds.width, paddle.bounds.x);
+        assert_eq!(ball.vx, -400.0 * BALL_BOUNCE_SPEEDUP);
+    }
+
+    #[test]
+    fn ignores_paddle_when_moving_away() {
+        let paddle = Paddle::new(25.0, 360.0);
+        let mut ball = ball_at(35.0, 360.0, 400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        assert_eq!(ball.vx, 400.0);
+    }
+
+    #[test]
+    fn ignores_paddle_when_not_touching() {
+        let paddle = Paddle::new(25.0, 360.0);
+        let mut ball = ball_at(35.0, 100.0, -400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        assert_eq!(ball.vx, -400.0);
+    }
+
+    #[test]
+    fn moving_paddle_imparts_spin_and_velocity() {
+        let mut paddle = Paddle::new(25.0, 360.0);
+        paddle.vy = 500.0;
+        let mut ball = ball_at(35.0, 360.0, -400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        assert!(ball.vy > 0.0);
+        assert!(ball.spin > 0.0);
+        assert!((speed(&ball) - 400.0 * BALL_BOUNCE_SPEEDUP).abs() < 0.01);
+    }
+
+    #[test]
+    fn still_paddle_imparts_no_spin() {
+        let paddle = Paddle::new(1255.0, 360.0);
+        let mut ball = ball_at(1245.0, 360.0, 400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        assert_eq!(ball.spin, 0.0);
+    }
+
+    #[test]
+    fn spin_is_clamped() {
+        let mut paddle = Paddle::new(25.0, 360.0);
+        paddle.vy = -1.0e6;
+        let mut ball = ball_at(35.0, 360.0, -400.0, 0.0);
+        ball.check_paddle_collision(&paddle);
+        assert_eq!(ball.spin, -BALL_MAX_SPIN);
+        assert!(ball.vy.atan2(ball.vx) >= -BALL_MAX_BOUNCE_ANGLE - 0.001);
+    }
+
+    #[test]
+    fn spin_curves_path_and_wears_off() {
+        let mut ball = ball_at(640.0, 360.0, 400.0, 0.0);
+        ball.spin = 1.0;
+        for _ in 0..30 {
+            ball.apply_spin(1.0 / 60.0);
+        }
+        assert!(ball.vy > 0.0);
+        assert!(ball.spin < 1.0 && ball.spin > 0.0);
+        assert!((speed(&ball) - 400.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn fast_ball_does_not_tunnel_through_paddle() {
+        // The ball moves 100px per update, far more than the paddle is wide.
+        let mut left = Paddle::new(25.0, 360.0);
+        let mut right = Paddle::new(1255.0, 360.0);
+        let mut ball = ball_at(100.0, 360.0, -6000.0, 0.0);
+        ball.update(&params(), &mut left, &mut right);
+        assert_eq!(ball.vx, 6000.0 * BALL_BOUNCE_SPEEDUP);
+        assert_eq!(right.score, 0);
+
+        // The ball bounced off the paddle and used the rest of the update moving right.
+        let contact = left.bounds.x + left.bounds.width;
+        let travelled = 100.0 - WIDTH / 2.0 - contact;
+        let expected = contact + (100.0 - travelled) * BALL_BOUNCE_SPEEDUP;
+        assert!((ball.bounds.x - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn fast_ball_bounces_off_wall_then_paddle_in_one_update() {
+        let mut left = Paddle::new(25.0, 360.0);
+        let mut right = Paddle::new(1255.0, 360.0);
+        left.bounds.y = 0.0;
+        let mut ball = ball_at(80.0, 20.0, -3000.0, -3000.0);
+        ball.update(&params(), &mut left, &mut right);
+        assert!(ball.vx > 0.0);
+        assert!(ball.bounds.x >= left.bounds.x + left.bounds.width);
+        assert!(ball.bounds.y >= 0.0);
+        assert_eq!(right.score, 0);
+    }
+
+    #[test]
+    fn right_player_scores_on_left_edge() {
+        let mut left = Paddle::new(25.0, 360.0);
+        let mut right = Paddle::new(1255.0, 360.0);
+        let mut ball = ball_at(-20.0, 360.0, -400.0, 0.0);
+        ball.check_goal(&params(), &mut left, &mut right);
+        assert_eq!(left.score, 0);
+        assert_eq!(right.score, 1);
+        assert_eq!(ball.bounds.x + ball.bounds.width / 2.0, 640.0);
+        assert_eq!(ball.bounds.y + ball.bounds.height / 2.0, 360.0);
+    }
+
+    #[test]
+    fn left_player_scores_on_right_edge() {
+        let mut left = Paddle::new(25.0, 360.0);
+        let mut right = Paddle::new(1255.0, 360.0);
+        let mut ball = ball_at(1300.0, 360.0, 400.0, 0.0);
+        ball.check_goal(&params(), &mut left, &mut right);
+        assert_eq!(left.score, 1);
+        assert_eq!(right.score, 0);
+    }
+
+    #[test]
+    fn goal_serves_toward_conceder_after_delay() {
+        let mut left = Paddle::new(25.0, 360.0);
+        let mut right = Paddle::new(1255.0, 360.0);
+        let mut ball = ball_at(1300.0, 360.0, 400.0, 0.0);
+        ball.check_goal(&params(), &mut left, &mut right);
+        assert!(ball.vx > 0.0);
+        assert_eq!(ball.start_timer, ServeSettings::default().delay);
+    }
+
+    #[test]
+    fn serve_stays_within_cone() {
+        let settings = ServeSettings::default();
+        let mut ball = Ball::with_serve_settings(640.0, 360.0, settings);
+        for _ in 0..100 {
+            ball.serve(Side::Left);
+            let speed = (ball.vx * ball.vx + ball.vy * ball.vy).sqrt();
+            let angle = (ball.vy / -ball.vx).atan();
+            assert!(ball.vx < 0.0);
+            assert!((speed - BALL_STARTING_SPEED).abs() < 0.01);
+            assert!(angle.abs() <= settings.max_angle);
+        }
+    }
+
+    #[test]
+    fn serve_without_cone_is_horizontal() {
+        let settings = ServeSettings { max_angle: 0.0, ..ServeSettings::default() };
+        let mut ball = Ball::with_serve_settings(640.0, 360.0, settings);
+        ball.serve(Side::Right);
+        assert_eq!(ball.vx, BALL_STARTING_SPEED);
+        assert_eq!(ball.vy, 0.0);
+    }
+
+    #[test]
+    fn alternating_serve_ignores_conceder() {
+        let settings = ServeSettings { direction: ServeDirection::Alternate, ..ServeSettings::default() };
+        let mut ball = Ball::with_serve_settings(640.0, 360.0, settings);
+        let first = ball.vx.signum();
+        ball.serve(Side::Left);
+        assert_eq!(ball.vx.signum(), -first);
+        ball.serve(Side::Left);
+        assert_eq!(ball.vx.signum(), first);
+    }
+
+    #[test]
+    fn no_goal_while_in_play() {
+        let mut left = Paddle::new(25.0, 360.0);
+        let mut right = Paddle::new(1255.0, 360.0);
+        let mut ball = ball_at(640.0, 360.0, 400.0, 0.0);
+        ball.check_goal(&params(), &mut left, &mut right);
+        assert_eq!(left.score + right.score, 0);
+        assert_eq!(ball.vx, 400.0);
+    }
+
+    #[test]
+    fn predicts_straight_flight() {
+        let ball = ball_at(600.0, 300.0, 600.0, 60.0);
+        let y = ball.predict_y_at(&params(), 1000.0).unwrap();
+        let expected = 300.0 + 60.0 * (1000.0 - WIDTH / 2.0 - 600.0) / 600.0;
+        assert!((y - expected).abs() < 0.01, "{} != {}", y, expected);
+    }
+
+    #[test]
+    fn predicts_wall_bounce() {
+        // Heading up at 45 degrees, 100 pixels from the top wall: the ball's top edge
+        // travels 100 pixels up and then 200 pixels back down.
+        let ball = ball_at(300.0, 100.0 + HEIGHT / 2.0, -300.0, -300.0);
+        let x = 300.0 - WIDTH / 2.0 - 300.0;
+        let y = ball.predict_y_at(&params(), x).unwrap();
+        assert!((y - (200.0 + HEIGHT / 2.0)).abs() < 5.0, "{}", y);
+
+        // Moving away, or not moving at all, never gets there.
+        assert_eq!(ball.predict_y_at(&params(), 1000.0), None);
+        assert_eq!(ball_at(300.0, 100.0, 0.0, 300.0).predict_y_at(&params(), x), None);
+    }
+
+    #[test]
+    fn prediction_matches_real_flight() {
+        let mut ball = ball_at(200.0, 600.0, 700.0, -500.0);
+        ball.spin = 0.8;
+        let x = 1100.0;
+        let predicted = ball.predict_y_at(&params(), x).unwrap();
+
+        // Fly the ball with paddles out of the way until it crosses the same point.
+        let mut left = Paddle::new(-1000.0, 360.0);
+        let mut right = Paddle::new(3000.0, 360.0);
+        while ball.bounds.x + ball.bounds.width < x {
+            ball.update(&params(), &mut left, &mut right);
+        }
+        let actual = ball.bounds.y + ball.bounds.height / 2.0;
+        assert!((predicted - actual).abs() < 15.0, "{} != {}", predicted, actual);
     }
 }
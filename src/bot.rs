@@ -0,0 +1,484 @@
+//! Lets a separate program play a paddle by exchanging JSON lines over its stdin and stdout,
+//! so that bots can be written in any language.
+//!
+//! # Protocol, version 1
+//!
+//! The game starts the bot as a child process and writes one JSON object per line to its stdin.
+//! The first line greets the bot, saying which paddle it plays and how the match is set up:
+//!
+//! ```text
+//! {"type":"hello","version":1,"side":"right","width":1280.0,"height":720.0,"tick_rate":60.0,"score_to_win":10}
+//! ```
+//!
+//! Before every tick, the game sends the state of the match. Positions are the top left corners
+//! of each object's bounds in pixels, with y pointing down the screen, and velocities are in
+//! pixels per second. Ticks are numbered from 0.
+//!
+//! ```text
+//! {"type":"tick","tick":0,
+//!  "ball":{"x":632.5,"y":352.5,"width":15.0,"height":15.0,"vx":-433.0,"vy":250.0},
+//!  "left":{"x":15.0,"y":310.0,"width":20.0,"height":100.0,"score":0},
+//!  "right":{"x":1245.0,"y":310.0,"width":20.0,"height":100.0,"score":0}}
+//! ```
+//!
+//! The bot answers each tick with a line naming the tick and how to move its paddle:
+//! `"up"`, `"down"` or `"stay"` at full speed, or a number from -1 for full speed up to 1 for
+//! full speed down.
+//!
+//! ```text
+//! {"tick":0,"action":"up"}
+//! {"tick":1,"action":-0.25}
+//! ```
+//!
+//! When the match is over, the game sends the winner, or `null` if it was stopped early,
+//! and closes the bot's stdin:
+//!
+//! ```text
+//! {"type":"end","winner":"left"}
+//! ```
+//!
+//! If an answer doesn't arrive within the timeout, the paddle stays still for that tick and the
+//! match carries on. Unless the game sets a timeout, it waits up to half a tick, so that a slow
+//! bot can't hold up the frame. Answers that arrive too late are skipped. A line that isn't a
+//! valid answer, or the bot exiting during the match, ends the match with an error. Bots must
+//! flush stdout after each answer and write nothing else to it; stderr can be used for logging.
+//!
+//! The version only goes up when a change to the messages could break existing bots.
+//! New fields may be added to the game's messages without changing it.
+
+use serde_json;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use {Paddle, PaddleInput, Side, Simulation, SCORE_TO_WIN};
+
+/// The version of the protocol described above.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The fraction of a tick that the game waits for the bot to answer, unless changed with
+/// `set_timeout`.
+pub const DEFAULT_TIMEOUT_TICKS: f32 = 0.5;
+
+// How long a bot gets to exit by itself after the match before it is killed.
+const EXIT_GRACE_TIME: Duration = Duration::from_millis(200);
+
+/// A message sent from the game to the bot.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    Hello {
+        version: u32,
+        side: &'static str,
+        width: f32,
+        height: f32,
+        tick_rate: f32,
+        score_to_win: u32,
+    },
+    Tick {
+        tick: u64,
+        ball: BallState,
+        left: PaddleState,
+        right: PaddleState,
+    },
+    End {
+        winner: Option<&'static str>,
+    },
+}
+
+#[derive(Serialize)]
+struct BallState {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    vx: f32,
+    vy: f32,
+}
+
+#[derive(Serialize)]
+struct PaddleState {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    score: u32,
+}
+
+impl PaddleState {
+    fn new(paddle: &Paddle) -> PaddleState {
+        let bounds = paddle.bounds;
+        PaddleState {
+            x: bounds.x,
+            y: bounds.y,
+            width: bounds.width,
+            height: bounds.height,
+            score: paddle.score,
+        }
+    }
+}
+
+/// The bot's answer to a tick.
+#[derive(Deserialize)]
+struct Answer {
+    tick: u64,
+    action: BotAction,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BotAction {
+    Named(NamedAction),
+    Axis(f32),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum NamedAction {
+    Up,
+    Down,
+    Stay,
+}
+
+impl BotAction {
+    fn to_input(&self) -> PaddleInput {
+        match *self {
+            BotAction::Named(NamedAction::Up) => PaddleInput { up: true, ..PaddleInput::default() },
+            BotAction::Named(NamedAction::Down) => PaddleInput { down: true, ..PaddleInput::default() },
+            BotAction::Named(NamedAction::Stay) => PaddleInput::default(),
+            BotAction::Axis(axis) => PaddleInput { axis: axis.clamp(-1.0, 1.0), ..PaddleInput::default() },
+        }
+    }
+}
+
+fn tick_message(tick: u64, simulation: &Simulation) -> Message {
+    let ball = simulation.ball();
+    let (bounds, (vx, vy)) = (ball.bounds(), ball.velocity());
+    Message::Tick {
+        tick,
+        ball: BallState {
+            x: bounds.x,
+            y: bounds.y,
+            width: bounds.width,
+            height: bounds.height,
+            vx,
+            vy,
+        },
+        left: PaddleState::new(simulation.left_paddle()),
+        right: PaddleState::new(simulation.right_paddle()),
+    }
+}
+
+/// A bot program playing one paddle.
+pub struct Bot {
+    side: Side,
+    child: Child,
+    stdin: Option<ChildStdin>,
+    answers: Receiver<io::Result<String>>,
+    timeout: Option<Duration>,
+    tick: u64,
+    timeouts: u64,
+    finished: bool,
+}
+
+impl Bot {
+    /// Starts the bot. The command is split on whitespace into the program to run and its
+    /// arguments. The bot is greeted once the match starts.
+    pub fn spawn(command: &str, side: Side) -> Result<Bot, BotError> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| BotError::Protocol("the bot command is empty".to_string()))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        // Read answers on another thread so that waiting for them can time out.
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, answers) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Bot {
+            side,
+            stdin: child.stdin.take(),
+            child,
+            answers,
+            timeout: None,
+            tick: 0,
+            timeouts: 0,
+            finished: false,
+        })
+    }
+
+    /// Changes how long to wait for each answer, such as to give the bot longer when the match
+    /// isn't being shown.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// The paddle that the bot plays.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// The number of ticks that the bot didn't answer in time.
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts
+    }
+
+    /// Sends the state of the match to the bot and waits for its move for the next tick of
+    /// `dt` seconds. Once the match has been won, the bot is told the winner and the paddle
+    /// stays still.
+    pub fn update(&mut self, simulation: &Simulation, dt: f32) -> Result<PaddleInput, BotError> {
+        if self.finished {
+            return Ok(PaddleInput::default());
+        }
+        if let Some(winner) = simulation.winner() {
+            self.finish(Some(winner))?;
+            return Ok(PaddleInput::default());
+        }
+
+        let tick = self.tick;
+        self.tick += 1;
+        if tick == 0 {
+            self.send(&Message::Hello {
+                version: PROTOCOL_VERSION,
+                side: self.side.name(),
+                width: simulation.width(),
+                height: simulation.height(),
+                tick_rate: 1.0 / dt,
+                score_to_win: SCORE_TO_WIN,
+            })?;
+        }
+        self.send(&tick_message(tick, simulation))?;
+
+        let timeout = self.timeout.unwrap_or_else(|| Duration::from_secs_f32(dt.max(0.0) * DEFAULT_TIMEOUT_TICKS));
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = match self.answers.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => line?,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.timeouts += 1;
+                    return Ok(PaddleInput::default());
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(BotError::Exited),
+            };
+            let answer: Answer = serde_json::from_str(&line)
+                .map_err(|err| BotError::Protocol(format!("invalid answer `{}`: {}", line, err)))?;
+            if answer.tick == tick {
+                return Ok(answer.action.to_input());
+            } else if answer.tick > tick {
+                return Err(BotError::Protocol(format!("answered tick {} before it was sent", answer.tick)));
+            }
+            // Skip answers to ticks that already timed out.
+        }
+    }
+
+    /// Tells the bot that the match is over and who won, if anybody, and lets it exit.
+    /// This happens by itself when the match is won, or when the bot is dropped.
+    pub fn finish(&mut self, winner: Option<Side>) -> Result<(), BotError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        let result = self.send(&Message::End { winner: winner.map(Side::name) });
+        self.stdin = None;
+        result
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), BotError> {
+        let stdin = self.stdin.as_mut().ok_or(BotError::Exited)?;
+        let mut line = serde_json::to_string(message).expect("messages always serialize");
+        line.push('\n');
+        match stdin.write_all(line.as_bytes()).and_then(|()| stdin.flush()) {
+            Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => Err(BotError::Exited),
+            result => Ok(result?),
+        }
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        // Tell the bot to exit, and give it a moment before killing it.
+        self.finish(None).unwrap_or(());
+        let start = Instant::now();
+        while start.elapsed() < EXIT_GRACE_TIME {
+            match self.child.try_wait() {
+                Ok(None) => thread::sleep(Duration::from_millis(5)),
+                _ => return,
+            }
+        }
+        self.child.kill().unwrap_or(());
+        self.child.wait().map(|_| ()).unwrap_or(());
+    }
+}
+
+/// Something that went wrong while talking to a bot.
+#[derive(Debug)]
+pub enum BotError {
+    Io(io::Error),
+    /// The bot sent something that doesn't follow the protocol.
+    Protocol(String),
+    /// The bot exited before the match was over.
+    Exited,
+}
+
+impl From<io::Error> for BotError {
+    fn from(err: io::Error) -> BotError {
+        BotError::Io(err)
+    }
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BotError::Io(ref err) => write!(f, "couldn't talk to the bot: {}", err),
+            BotError::Protocol(ref message) => write!(f, "the bot broke the protocol: {}", message),
+            BotError::Exited => write!(f, "the bot exited before the match was over"),
+        }
+    }
+}
+
+impl StdError for BotError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            BotError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use {GAME_HEIGHT, GAME_WIDTH};
+
+    fn simulation() -> Simulation {
+        Simulation::new(GAME_WIDTH as f32, GAME_HEIGHT as f32)
+    }
+
+    /// A shell script for a bot in a directory of its own, which is removed when dropped.
+    struct Script {
+        dir: PathBuf,
+        command: String,
+    }
+
+    impl Drop for Script {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.dir).unwrap_or(());
+        }
+    }
+
+    fn script(body: &str) -> Script {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("rusty-pong-bot-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst));
+        let dir = env::temp_dir().join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bot.sh");
+        fs::write(&path, body).unwrap();
+        Script { command: format!("sh {}", path.display()), dir }
+    }
+
+    #[test]
+    fn tick_message_matches_schema() {
+        let sim = simulation();
+        let json = serde_json::to_value(tick_message(3, &sim)).unwrap();
+        assert_eq!(json["type"], "tick");
+        assert_eq!(json["tick"], 3);
+        assert_eq!(json["ball"]["vx"], sim.ball().velocity().0 as f64);
+        assert_eq!(json["right"]["x"], sim.right_paddle().bounds.x as f64);
+        assert_eq!(json["left"]["score"], 0);
+    }
+
+    #[test]
+    fn parses_answers() {
+        let answer: Answer = serde_json::from_str(r#"{"tick":1,"action":"down"}"#).unwrap();
+        assert!(answer.action.to_input().down);
+        let answer: Answer = serde_json::from_str(r#"{"tick":1,"action":-2.5}"#).unwrap();
+        assert_eq!(answer.action.to_input().axis, -1.0);
+        assert!(serde_json::from_str::<Answer>(r#"{"tick":1,"action":"jump"}"#).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bot_moves_paddle() {
+        let script = script(r#"
+            read hello
+            n=0
+            while read tick; do
+                echo "{\"tick\":$n,\"action\":\"up\"}"
+                n=$((n + 1))
+            done
+        "#);
+        let sim = simulation();
+        let mut bot = Bot::spawn(&script.command, Side::Right).unwrap();
+        bot.set_timeout(Duration::from_secs(5));
+        for _ in 0..3 {
+            assert!(bot.update(&sim, 1.0 / 60.0).unwrap().up);
+        }
+        assert_eq!(bot.timeouts(), 0);
+        bot.finish(None).unwrap();
+        assert_eq!(bot.update(&sim, 1.0 / 60.0).unwrap(), PaddleInput::default());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slow_bot_times_out() {
+        let script = script("while read line; do sleep 1; done\n");
+        let sim = simulation();
+        let mut bot = Bot::spawn(&script.command, Side::Left).unwrap();
+
+        // By default the wait is a fraction of the tick.
+        let start = Instant::now();
+        assert_eq!(bot.update(&sim, 0.01).unwrap(), PaddleInput::default());
+        assert!(start.elapsed() < Duration::from_millis(500), "waited {:?}", start.elapsed());
+        assert_eq!(bot.timeouts(), 1);
+
+        bot.set_timeout(Duration::from_millis(20));
+        let start = Instant::now();
+        assert_eq!(bot.update(&sim, 0.01).unwrap(), PaddleInput::default());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(bot.timeouts(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_answers_are_errors() {
+        let script = script("while read line; do echo nonsense; done\n");
+        let sim = simulation();
+        let mut bot = Bot::spawn(&script.command, Side::Left).unwrap();
+        bot.set_timeout(Duration::from_secs(5));
+        match bot.update(&sim, 1.0 / 60.0) {
+            Err(BotError::Protocol(message)) => assert!(message.contains("nonsense"), "{}", message),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exited_bot_is_an_error() {
+        let script = script("exit 0\n");
+        let sim = simulation();
+        let result = Bot::spawn(&script.command, Side::Left).and_then(|mut bot| {
+            bot.set_timeout(Duration::from_secs(5));
+            bot.update(&sim, 1.0 / 60.0)
+        });
+        match result {
+            Err(BotError::Exited) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Plays or watches a match on a dedicated server; see the server module.
+//!
+//! What the server sends is a little out of date by the time it arrives. So that the player's own
+//! paddle still answers the controls straight away, the client moves it by the inputs that the
+//! server hasn't played yet, predicting where the server will put it. Each new snapshot replaces
+//! the prediction, which corrects any mistakes in it. The ball and the other paddle are shown
+//! as the server last saw them.
+//!
+//! Spectators are mostly sent deltas, so they keep the latest states they were sent to read them
+//! against.
+
+use net::{Link, NetError, Packet, DELTA_HISTORY, MAX_INPUTS_PER_PACKET, POLL_INTERVAL, PROTOCOL_VERSION, RETRY_INTERVAL, TIMEOUT};
+use state;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Instant;
+use {InputMap, PaddleInput, Side, Simulation, GAME_HEIGHT, GAME_WIDTH};
+
+/// A connection to a server, as a player or a spectator.
+pub struct Client {
+    link: Link,
+    server: SocketAddr,
+    side: Option<Side>,
+    tick_rate: f32,
+    last_heard: Instant,
+
+    // The match in the latest snapshot from the server, the number of ticks it had played, and
+    // the latest states sent, in order, with their ticks.
+    snapshot: Simulation,
+    server_tick: usize,
+    states: VecDeque<(usize, Vec<u8>)>,
+
+    // The id of the match in the snapshot, and of the match before it, which any packets still
+    // on their way from are ignored.
+    match_id: Option<u64>,
+    previous_match_id: Option<u64>,
+
+    // The snapshot with the player's paddle moved by the inputs that the server hasn't played.
+    predicted: Simulation,
+
+    // The inputs that the server hasn't played, the first of which is input number `acked`.
+    pending: VecDeque<PaddleInput>,
+    acked: usize,
+}
+
+impl Client {
+    /// Connects to the server at the address, to play if there is a free paddle or to watch.
+    pub fn connect<A: ToSocketAddrs>(mut link: Link, addr: A, spectate: bool) -> Result<Client, NetError> {
+        let server = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to"))?;
+        let start = Instant::now();
+        let mut last_join = None;
+        while start.elapsed() < TIMEOUT {
+            if last_join.map_or(true, |time: Instant| time.elapsed() >= RETRY_INTERVAL) {
+                link.send_to(&Packet::Join { version: PROTOCOL_VERSION, spectate }, server)?;
+                last_join = Some(Instant::now());
+            }
+            match link.recv_from()? {
+                Some((Packet::Accept { version, side, tick_rate }, addr)) if addr == server => {
+                    if version != PROTOCOL_VERSION {
+                        return Err(NetError::VersionMismatch(version));
+                    }
+                    return Ok(Client::new(link, server, side, tick_rate));
+                }
+                Some(_) => (),
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+        Err(NetError::TimedOut)
+    }
+
+    fn new(link: Link, server: SocketAddr, side: Option<Side>, tick_rate: f32) -> Client {
+        let simulation = Simulation::new(GAME_WIDTH as f32, GAME_HEIGHT as f32);
+        Client {
+            link,
+            server,
+            side,
+            tick_rate,
+            last_heard: Instant::now(),
+            snapshot: simulation.clone(),
+            server_tick: 0,
+            states: VecDeque::new(),
+            match_id: None,
+            previous_match_id: None,
+            predicted: simulation,
+            pending: VecDeque::new(),
+            acked: 0,
+        }
+    }
+
+    /// The paddle that this client plays, or `None` if it is spectating.
+    pub fn side(&self) -> Option<Side> {
+        self.side
+    }
+
+    /// The tick rate that the server plays at.
+    pub fn tick_rate(&self) -> f32 {
+        self.tick_rate
+    }
+
+    /// Gives the paddles the bindings for the players, so that the simulation can read input.
+    pub fn set_input_map(&mut self, input_map: &InputMap) {
+        self.snapshot.set_input_map(input_map);
+        self.predicted.set_input_map(input_map);
+    }
+
+    /// The match to show, with the player's own paddle where it is predicted to be.
+    pub fn simulation(&self) -> &Simulation {
+        &self.predicted
+    }
+
+    /// The match as the server last saw it.
+    pub fn snapshot(&self) -> &Simulation {
+        &self.snapshot
+    }
+
+    /// The number of ticks that the server had played as of the latest snapshot.
+    /// This is 0 until both paddles have players.
+    pub fn server_tick(&self) -> usize {
+        self.server_tick
+    }
+
+    /// Whether the server has played every input sent so far.
+    pub fn is_caught_up(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Sends the input for the player's paddle for the next tick, and moves the paddle straight
+    /// away. Spectators and players waiting for the match to start send no input.
+    pub fn update(&mut self, input: PaddleInput) -> Result<(), NetError> {
+        self.receive()?;
+        if let Some(side) = self.side {
+            if self.server_tick > 0 {
+                self.pending.push_back(input);
+                let params = self.predicted.update_params(1.0 / self.tick_rate);
+                self.predicted.paddle_mut(side).update(&params, &input);
+            }
+        }
+        self.send_inputs()
+    }
+
+    /// Takes in any snapshots from the server, and sends it any inputs it is missing.
+    pub fn poll(&mut self) -> Result<(), NetError> {
+        self.receive()?;
+        self.send_inputs()
+    }
+
+    fn receive(&mut self) -> Result<(), NetError> {
+        let mut changed = false;
+        while let Some((packet, addr)) = self.link.recv_from()? {
+            if addr != self.server {
+                continue;
+            }
+            self.last_heard = Instant::now();
+            match packet {
+                Packet::Snapshot { match_id, tick, ack, state } => {
+                    if !self.take_state(match_id, tick as usize, state) {
+                        continue;
+                    }
+                    while self.acked < ack as usize && self.pending.pop_front().is_some() {
+                        self.acked += 1;
+                    }
+                    changed = true;
+                }
+                Packet::Delta { match_id, tick, base, delta } => {
+                    // Deltas can only be read against a state from the same match.
+                    if self.match_id != Some(match_id) {
+                        continue;
+                    }
+                    let state = self
+                        .states
+                        .iter()
+                        .find(|&&(tick, _)| tick == base as usize)
+                        .and_then(|(_, base)| state::read_delta(&mut &delta[..], base));
+                    if let Some(state) = state {
+                        changed |= self.take_state(match_id, tick as usize, state);
+                    }
+                }
+                _ => (),
+            }
+        }
+        if self.last_heard.elapsed() > TIMEOUT {
+            return Err(NetError::TimedOut);
+        }
+
+        if changed {
+            self.predict();
+        }
+        Ok(())
+    }
+
+    /// Makes a state sent by the server the latest snapshot, unless it is older than the latest,
+    /// is from the previous match, or can't be read. Returns whether it was taken.
+    fn take_state(&mut self, match_id: u64, tick: usize, state: Vec<u8>) -> bool {
+        // Skip states that arrive out of order. A new match starts over from whatever tick
+        // arrives first.
+        let new_match = self.match_id != Some(match_id);
+        if new_match && self.previous_match_id == Some(match_id) || !new_match && tick < self.server_tick {
+            return false;
+        }
+        let mut snapshot = self.snapshot.clone();
+        if snapshot.read_state(&state).is_none() {
+            return false;
+        }
+        if new_match {
+            self.previous_match_id = self.match_id;
+            self.match_id = Some(match_id);
+            self.states.clear();
+        }
+        self.snapshot = snapshot;
+        self.server_tick = tick;
+        self.states.push_back((tick, state));
+        if self.states.len() > DELTA_HISTORY {
+            self.states.pop_front();
+        }
+        true
+    }
+
+    /// Predicts where the player's paddle will be once the server plays the pending inputs.
+    fn predict(&mut self) {
+        self.predicted.clone_from(&self.snapshot);
+        if let Some(side) = self.side {
+            let params = self.predicted.update_params(1.0 / self.tick_rate);
+            for input in &self.pending {
+                self.predicted.paddle_mut(side).update(&params, input);
+            }
+        }
+    }
+
+    fn send_inputs(&mut self) -> Result<(), NetError> {
+        // Spectators send empty packets, which let the server know they're still watching.
+        let packet = Packet::Inputs {
+            ack: self.server_tick as u32,
+            start: self.acked as u32,
+            inputs: self.pending.iter().take(MAX_INPUTS_PER_PACKET).cloned().collect(),
+        };
+        self.link.send_to(&packet, self.server)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use net::NetConditions;
+    use server::Server;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const TICK_RATE: f32 = 240.0;
+
+    fn connect(addr: SocketAddr, spectate: bool, conditions: NetConditions) -> Client {
+        let mut link = Link::bind("127.0.0.1:0").unwrap();
+        link.set_conditions(conditions);
+        Client::connect(link, addr, spectate).unwrap()
+    }
+
+    #[test]
+    fn players_predict_their_paddles_and_agree_with_the_server() {
+        let server_link = Link::bind("127.0.0.1:0").unwrap();
+        let addr = server_link.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let server_stop = stop.clone();
+        let server = thread::spawn(move || {
+            let mut server = Server::new(server_link, TICK_RATE);
+            server.run_while(|_| !server_stop.load(Ordering::SeqCst)).unwrap();
+            server
+        });
+
+        let conditions = NetConditions {
+            latency: Duration::from_millis(20),
+            jitter: Duration::from_millis(5),
+            loss: 0.1,
+        };
+        let mut left = connect(addr, false, conditions);
+        let mut right = connect(addr, false, conditions);
+        let mut spectator = connect(addr, false, NetConditions::default());
+        assert_eq!(left.side(), Some(Side::Left));
+        assert_eq!(right.side(), Some(Side::Right));
+        assert_eq!(spectator.side(), None);
+
+        // The left player holds up and the right holds down.
+        let up = PaddleInput { up: true, ..PaddleInput::default() };
+        let down = PaddleInput { down: true, ..PaddleInput::default() };
+        let mut predicted_ahead = false;
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) {
+            left.update(up).unwrap();
+            right.update(down).unwrap();
+            spectator.update(PaddleInput::default()).unwrap();
+            let predicted = left.simulation().left_paddle().bounds.y;
+            predicted_ahead |= predicted < left.snapshot().left_paddle().bounds.y;
+            thread::sleep(Duration::from_secs_f32(1.0 / TICK_RATE));
+        }
+        assert!(predicted_ahead, "the left paddle was never predicted ahead of the server");
+
+        // Once the server has played every input, the predictions match it.
+        let start = Instant::now();
+        while !left.is_caught_up() || !right.is_caught_up() {
+            assert!(start.elapsed() < Duration::from_secs(5), "the server never caught up");
+            left.poll().unwrap();
+            right.poll().unwrap();
+            spectator.poll().unwrap();
+            thread::sleep(POLL_INTERVAL);
+        }
+        stop.store(true, Ordering::SeqCst);
+        let server = server.join().unwrap();
+
+        assert!(server.tick() > 0);
+        let server_left = server.simulation().left_paddle().bounds.y;
+        let server_right = server.simulation().right_paddle().bounds.y;
+        assert_eq!(server_left, 0.0);
+        assert_eq!(server_right, server.simulation().height() - server.simulation().right_paddle().bounds.height);
+        assert_eq!(left.simulation().left_paddle().bounds.y, server_left);
+        assert_eq!(right.simulation().right_paddle().bounds.y, server_right);
+        assert!(spectator.server_tick() > 0);
+        assert_eq!(spectator.simulation().left_paddle().bounds.y, server_left);
+    }
+
+    #[test]
+    fn new_matches_are_told_apart_by_id_rather_than_tick() {
+        let link = Link::bind("127.0.0.1:0").unwrap();
+        let addr = link.local_addr().unwrap();
+        let mut client = Client::new(link, addr, None, TICK_RATE);
+        let mut state = Vec::new();
+        client.snapshot().write_state(&mut state);
+
+        assert!(client.take_state(1, 100, state.clone()));
+        assert!(!client.take_state(1, 90, state.clone()));
+
+        // A new match is taken up partway through, without having seen its first tick.
+        assert!(client.take_state(2, 50, state.clone()));
+        assert_eq!(client.server_tick(), 50);
+
+        // Late packets from the old match don't take it back.
+        assert!(!client.take_state(1, 120, state.clone()));
+        assert!(!client.take_state(2, 0, state));
+        assert_eq!(client.server_tick(), 50);
+    }
+}
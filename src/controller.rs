@@ -0,0 +1,93 @@
+//! Named controllers that can play a paddle without a person, such as the AI or a bot program.
+
+use bot::{Bot, BotError};
+use std::fmt;
+use {AiController, Difficulty, GameRng, PaddleInput, Side, Simulation};
+
+/// A kind of controller, which can be named on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControllerKind {
+    /// Leaves the paddle standing still.
+    Idle,
+    Ai(Difficulty),
+    /// A bot program started with the given command.
+    Bot(String),
+}
+
+impl ControllerKind {
+    /// Parses a controller from its name: `idle`, `easy`, `medium`, `hard`,
+    /// or `bot:` followed by the command that starts the bot.
+    pub fn from_name(name: &str) -> Option<ControllerKind> {
+        if let Some(command) = name.strip_prefix("bot:") {
+            let command = command.trim();
+            return if command.is_empty() { None } else { Some(ControllerKind::Bot(command.to_string())) };
+        }
+        match name {
+            "idle" => Some(ControllerKind::Idle),
+            _ => Difficulty::from_name(name).map(ControllerKind::Ai),
+        }
+    }
+
+    /// Creates a controller of this kind for one paddle, with any randomness picked by `rng`.
+    pub fn create(&self, side: Side, rng: GameRng) -> Result<Controller, BotError> {
+        Ok(match *self {
+            ControllerKind::Idle => Controller::Idle(side),
+            ControllerKind::Ai(difficulty) => Controller::Ai(AiController::with_rng(side, difficulty.settings(), rng)),
+            ControllerKind::Bot(ref command) => Controller::Bot(Bot::spawn(command, side)?),
+        })
+    }
+}
+
+impl fmt::Display for ControllerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ControllerKind::Idle => f.write_str("idle"),
+            ControllerKind::Ai(Difficulty::Easy) => f.write_str("easy"),
+            ControllerKind::Ai(Difficulty::Medium) => f.write_str("medium"),
+            ControllerKind::Ai(Difficulty::Hard) => f.write_str("hard"),
+            ControllerKind::Bot(ref command) => write!(f, "bot:{}", command),
+        }
+    }
+}
+
+/// Plays one paddle in place of a person.
+pub enum Controller {
+    Idle(Side),
+    Ai(AiController),
+    Bot(Bot),
+}
+
+impl Controller {
+    /// The side of the court that the controller plays.
+    pub fn side(&self) -> Side {
+        match *self {
+            Controller::Idle(side) => side,
+            Controller::Ai(ref ai) => ai.side(),
+            Controller::Bot(ref bot) => bot.side(),
+        }
+    }
+
+    /// Looks at the match and decides how to move for the next tick of `dt` seconds.
+    pub fn update(&mut self, simulation: &Simulation, dt: f32) -> Result<PaddleInput, BotError> {
+        match *self {
+            Controller::Idle(_) => Ok(PaddleInput::default()),
+            Controller::Ai(ref mut ai) => Ok(ai.update(simulation, dt)),
+            Controller::Bot(ref mut bot) => bot.update(simulation, dt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names() {
+        for name in &["idle", "easy", "medium", "hard", "bot:python3 my_bot.py"] {
+            let kind = ControllerKind::from_name(name).unwrap();
+            assert_eq!(kind.to_string(), *name);
+        }
+        assert_eq!(ControllerKind::from_name("bot:"), None);
+        assert_eq!(ControllerKind::from_name("impossible"), None);
+    }
+}
@@ -0,0 +1,511 @@
+//! Controls for the paddles and the game, and the bindings file that maps inputs to them.
+//!
+//! The simulation doesn't know about any particular windowing library, so frontends translate
+//! their own key and mouse events into an `InputState` before handing them over.
+//!
+//! A bindings file has a section for each player, one for the game as a whole, and one for
+//! watching replays. Each line binds one or more inputs to an action:
+//!
+//! ```text
+//! [left]
+//! move_up = W
+//! move_down = S
+//! serve = Space, MouseLeft
+//!
+//! [right]
+//! move_up = Up
+//! move_down = Down
+//! serve = Return
+//!
+//! [game]
+//! pause = P
+//! quit = Escape
+//!
+//! [replay]
+//! step_back = Left
+//! step_forward = Right
+//! seek_back = J
+//! seek_forward = L
+//! slower = Z
+//! faster = X
+//!
+//! [gamepad]
+//! deadzone = 0.15
+//! sensitivity = 1.0
+//! ```
+//!
+//! The `[gamepad]` section holds the `StickSettings` for every gamepad's analog stick.
+//!
+//! No paddle follows the pointer unless `move_to = PointerY` is added to its section.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error as StdError;
+use std::fmt;
+use {PaddleInput, StickSettings};
+
+/// A key on the keyboard that can be bound to an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    A, B, C, D, E, F, G, H, I, J, K, L, M,
+    N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    Up, Down, Left, Right,
+    Space, Return, Escape, Tab, Back,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+}
+
+impl Key {
+    /// Looks up a key by the name of its variant, ignoring case.
+    pub fn from_name(name: &str) -> Option<Key> {
+        macro_rules! lookup {
+            ($($name:ident),*) => {
+                $(if name.eq_ignore_ascii_case(stringify!($name)) {
+                    return Some(Key::$name);
+                })*
+            }
+        }
+
+        lookup!(
+            A, B, C, D, E, F, G, H, I, J, K, L, M,
+            N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+            Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+            Up, Down, Left, Right,
+            Space, Return, Escape, Tab, Back,
+            LShift, RShift, LControl, RControl, LAlt, RAlt
+        );
+        None
+    }
+}
+
+/// A mouse button that can be bound to an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// Anything that can be bound to an action: a button that is either held or not,
+/// or an axis with a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Trigger {
+    Key(Key),
+    Mouse(MouseButton),
+    /// The height of the mouse cursor or a touch, in game coordinates.
+    PointerY,
+}
+
+impl Trigger {
+    /// Parses an input name from a bindings file.
+    pub fn from_name(name: &str) -> Option<Trigger> {
+        if name.eq_ignore_ascii_case("PointerY") {
+            Some(Trigger::PointerY)
+        } else if name.eq_ignore_ascii_case("MouseLeft") {
+            Some(Trigger::Mouse(MouseButton::Left))
+        } else if name.eq_ignore_ascii_case("MouseRight") {
+            Some(Trigger::Mouse(MouseButton::Right))
+        } else if name.eq_ignore_ascii_case("MouseMiddle") {
+            Some(Trigger::Mouse(MouseButton::Middle))
+        } else {
+            Key::from_name(name).map(Trigger::Key)
+        }
+    }
+
+    /// Axes have a position rather than being held down.
+    pub fn is_axis(self) -> bool {
+        self == Trigger::PointerY
+    }
+}
+
+/// Something a player can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Moves a paddle up while held.
+    MoveUp,
+    /// Moves a paddle down while held.
+    MoveDown,
+    /// Moves a paddle towards the position of an axis.
+    MoveTo,
+    /// Serves the ball straight away instead of waiting for the countdown.
+    Serve,
+    /// Pauses or resumes the game.
+    Pause,
+    /// Exits the game.
+    Quit,
+    /// Pauses a replay and goes back one tick.
+    StepBack,
+    /// Pauses a replay and goes forward one tick.
+    StepForward,
+    /// Jumps back a few seconds in a replay.
+    SeekBack,
+    /// Jumps forward a few seconds in a replay.
+    SeekForward,
+    /// Plays a replay more slowly.
+    Slower,
+    /// Plays a replay more quickly.
+    Faster,
+}
+
+impl Action {
+    /// Parses an action name from a bindings file.
+    pub fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "move_up" => Some(Action::MoveUp),
+            "move_down" => Some(Action::MoveDown),
+            "move_to" => Some(Action::MoveTo),
+            "serve" => Some(Action::Serve),
+            "pause" => Some(Action::Pause),
+            "quit" => Some(Action::Quit),
+            "step_back" => Some(Action::StepBack),
+            "step_forward" => Some(Action::StepForward),
+            "seek_back" => Some(Action::SeekBack),
+            "seek_forward" => Some(Action::SeekForward),
+            "slower" => Some(Action::Slower),
+            "faster" => Some(Action::Faster),
+            _ => None,
+        }
+    }
+
+    /// Paddle actions are bound per player; the rest apply to the whole game or to replays.
+    pub fn is_paddle_action(self) -> bool {
+        matches!(self, Action::MoveUp | Action::MoveDown | Action::MoveTo | Action::Serve)
+    }
+
+    /// Replay actions only do anything while watching a replay.
+    pub fn is_replay_action(self) -> bool {
+        matches!(
+            self,
+            Action::StepBack | Action::StepForward | Action::SeekBack | Action::SeekForward | Action::Slower | Action::Faster
+        )
+    }
+
+    /// Describes the section of a bindings file that the action belongs in.
+    fn section(self) -> &'static str {
+        if self.is_paddle_action() {
+            "a `[left]` or `[right]` section"
+        } else if self.is_replay_action() {
+            "the `[replay]` section"
+        } else {
+            "the `[game]` section"
+        }
+    }
+}
+
+/// The keys and mouse buttons that are currently held down, and where the pointer is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputState {
+    pub keys: HashSet<Key>,
+    pub mouse_buttons: HashSet<MouseButton>,
+    pub pointer_y: Option<f32>,
+}
+
+impl InputState {
+    pub fn new() -> InputState {
+        InputState::default()
+    }
+
+    /// Returns whether a button is held down. Axes are never held.
+    pub fn is_held(&self, trigger: Trigger) -> bool {
+        match trigger {
+            Trigger::Key(key) => self.keys.contains(&key),
+            Trigger::Mouse(button) => self.mouse_buttons.contains(&button),
+            Trigger::PointerY => false,
+        }
+    }
+}
+
+/// A set of inputs bound to actions, for one player or for the game.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bindings {
+    bindings: Vec<(Trigger, Action)>,
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings::default()
+    }
+
+    /// W and S, for the player on the left side of the keyboard.
+    pub fn wasd() -> Bindings {
+        let mut bindings = Bindings::new();
+        bindings.bind(Trigger::Key(Key::W), Action::MoveUp);
+        bindings.bind(Trigger::Key(Key::S), Action::MoveDown);
+        bindings.bind(Trigger::Key(Key::Space), Action::Serve);
+        bindings
+    }
+
+    /// The up and down arrow keys, for the player on the right side of the keyboard.
+    pub fn arrows() -> Bindings {
+        let mut bindings = Bindings::new();
+        bindings.bind(Trigger::Key(Key::Up), Action::MoveUp);
+        bindings.bind(Trigger::Key(Key::Down), Action::MoveDown);
+        bindings.bind(Trigger::Key(Key::Return), Action::Serve);
+        bindings
+    }
+
+    /// Adds a binding. An input can be bound to more than one action.
+    pub fn bind(&mut self, trigger: Trigger, action: Action) {
+        self.bindings.push((trigger, action));
+    }
+
+    /// Returns the action that an input is bound to, if any.
+    pub fn action_for(&self, trigger: Trigger) -> Option<Action> {
+        self.bindings.iter().find(|&&(t, _)| t == trigger).map(|&(_, action)| action)
+    }
+
+    /// Returns the inputs bound to an action.
+    pub fn triggers_for(&self, action: Action) -> Vec<Trigger> {
+        self.bindings.iter().filter(|&&(_, a)| a == action).map(|&(trigger, _)| trigger).collect()
+    }
+
+    /// Returns whether any input bound to the action is held down.
+    pub fn is_held(&self, action: Action, state: &InputState) -> bool {
+        self.bindings.iter().any(|&(trigger, a)| a == action && state.is_held(trigger))
+    }
+
+    /// Reads the paddle controls from the current input state.
+    pub fn read(&self, state: &InputState) -> PaddleInput {
+        let follows_pointer = self.bindings.contains(&(Trigger::PointerY, Action::MoveTo));
+        PaddleInput {
+            up: self.is_held(Action::MoveUp, state),
+            down: self.is_held(Action::MoveDown, state),
+            axis: 0.0,
+            target_y: if follows_pointer { state.pointer_y } else { None },
+            serve: self.is_held(Action::Serve, state),
+        }
+    }
+}
+
+/// The bindings for both players and the game, and the gamepad settings,
+/// as loaded from a bindings file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputMap {
+    pub left: Bindings,
+    pub right: Bindings,
+    pub game: Bindings,
+    pub replay: Bindings,
+    pub stick: StickSettings,
+}
+
+impl Default for InputMap {
+    /// The left player uses W and S, and the right player uses the arrow keys. The pointer isn't
+    /// bound, so the mouse only serves.
+    /// P pauses and Escape quits. Replays step with the left and right arrow keys, seek with
+    /// J and L, and change speed with Z and X.
+    fn default() -> InputMap {
+        let mut left = Bindings::wasd();
+        left.bind(Trigger::Mouse(MouseButton::Left), Action::Serve);
+        let mut game = Bindings::new();
+        game.bind(Trigger::Key(Key::P), Action::Pause);
+        game.bind(Trigger::Key(Key::Escape), Action::Quit);
+        let mut replay = Bindings::new();
+        replay.bind(Trigger::Key(Key::Left), Action::StepBack);
+        replay.bind(Trigger::Key(Key::Right), Action::StepForward);
+        replay.bind(Trigger::Key(Key::J), Action::SeekBack);
+        replay.bind(Trigger::Key(Key::L), Action::SeekForward);
+        replay.bind(Trigger::Key(Key::Z), Action::Slower);
+        replay.bind(Trigger::Key(Key::X), Action::Faster);
+        InputMap { left, right: Bindings::arrows(), game, replay, stick: StickSettings::default() }
+    }
+}
+
+impl InputMap {
+    /// Parses and validates a bindings file.
+    pub fn parse(text: &str) -> Result<InputMap, ParseError> {
+        let mut left = Bindings::new();
+        let mut right = Bindings::new();
+        let mut game = Bindings::new();
+        let mut replay = Bindings::new();
+        let mut stick = StickSettings::default();
+        let mut section = None;
+        let mut bound_on_line = HashMap::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| ParseError { line: Some(line_number), message };
+
+            // Skip comments and blank lines.
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim();
+                match name {
+                    "left" | "right" | "game" | "replay" | "gamepad" => section = Some(name),
+                    _ => return Err(error(format!(
+                        "unknown section `[{}]`, expected `[left]`, `[right]`, `[game]`, `[replay]` or `[gamepad]`",
+                        name
+                    ))),
+                }
+                continue;
+            }
+
+            let (action_name, inputs) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => return Err(error(format!("expected `action = input`, found `{}`", line))),
+            };
+
+            if section == Some("gamepad") {
+                let setting = action_name;
+                if setting != "deadzone" && setting != "sensitivity" {
+                    return Err(error(format!("unknown gamepad setting `{}`", setting)));
+                }
+                let value: f32 = inputs.parse()
+                    .map_err(|_| error(format!("`{}` must be a number, not `{}`", setting, inputs)))?;
+                if setting == "deadzone" {
+                    if !(0.0..1.0).contains(&value) {
+                        return Err(error("`deadzone` must be at least 0 and less than 1".to_string()));
+                    }
+                    stick.deadzone = value;
+                } else {
+                    if value.is_nan() || value <= 0.0 {
+                        return Err(error("`sensitivity` must be greater than 0".to_string()));
+                    }
+                    stick.sensitivity = value;
+                }
+                continue;
+            }
+            let action = Action::from_name(action_name)
+                .ok_or_else(|| error(format!("unknown action `{}`", action_name)))?;
+            let (bindings, belongs) = match section {
+                Some("left") => (&mut left, action.is_paddle_action()),
+                Some("right") => (&mut right, action.is_paddle_action()),
+                Some("replay") => (&mut replay, action.is_replay_action()),
+                Some(_) => (&mut game, !action.is_paddle_action() && !action.is_replay_action()),
+                None => return Err(error("bindings must come after a section header such as `[left]`".to_string())),
+            };
+            if !belongs {
+                return Err(error(format!("`{}` belongs in {}", action_name, action.section())));
+            }
+
+            if inputs.is_empty() {
+                return Err(error(format!("nothing is bound to `{}`", action_name)));
+            }
+            for name in inputs.split(',').map(str::trim) {
+                let trigger = Trigger::from_name(name)
+                    .ok_or_else(|| error(format!("unknown input `{}`", name)))?;
+                if trigger.is_axis() != (action == Action::MoveTo) {
+                    let expected = if action == Action::MoveTo { "an axis such as `PointerY`" } else { "a key or mouse button" };
+                    return Err(error(format!("`{}` must be bound to {}, not `{}`", action_name, expected, name)));
+                }
+                if let Some(first) = bound_on_line.insert(trigger, line_number) {
+                    return Err(error(format!("`{}` is already bound on line {}", name, first)));
+                }
+                bindings.bind(trigger, action);
+            }
+        }
+
+        if game.triggers_for(Action::Quit).is_empty() {
+            return Err(ParseError { line: None, message: "nothing is bound to `quit`".to_string() });
+        }
+        Ok(InputMap { left, right, game, replay, stick })
+    }
+}
+
+/// A problem with a bindings file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// The line the problem was found on, starting from 1, or `None` if it affects the whole file.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl StdError for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with_keys(keys: &[Key]) -> InputState {
+        InputState { keys: keys.iter().cloned().collect(), ..InputState::default() }
+    }
+
+    fn parse_error(text: &str) -> String {
+        InputMap::parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn reads_only_bound_keys() {
+        let state = state_with_keys(&[Key::W, Key::Down]);
+        assert_eq!(Bindings::wasd().read(&state), PaddleInput { up: true, ..PaddleInput::default() });
+        assert_eq!(Bindings::arrows().read(&state), PaddleInput { down: true, ..PaddleInput::default() });
+    }
+
+    #[test]
+    fn pointer_only_moves_bound_paddle() {
+        let state = InputState { pointer_y: Some(100.0), ..InputState::default() };
+        let mut map = InputMap::default();
+        assert_eq!(map.left.read(&state).target_y, None);
+
+        map.left.bind(Trigger::PointerY, Action::MoveTo);
+        assert_eq!(map.left.read(&state).target_y, Some(100.0));
+        assert_eq!(map.right.read(&state).target_y, None);
+    }
+
+    #[test]
+    fn parses_bindings_file() {
+        let map = InputMap::parse("
+            # Swap the players around.
+            [left]
+            move_up = Up
+            move_down = down, MouseRight
+
+            [right]
+            move_to = PointerY
+            serve = MouseLeft
+
+            [game]
+            quit = Escape, Q  # either works
+
+            [gamepad]
+            sensitivity = 1.5
+        ").unwrap();
+        assert_eq!(map.left.triggers_for(Action::MoveDown), vec![Trigger::Key(Key::Down), Trigger::Mouse(MouseButton::Right)]);
+        assert_eq!(map.right.action_for(Trigger::PointerY), Some(Action::MoveTo));
+        assert_eq!(map.game.action_for(Trigger::Key(Key::Q)), Some(Action::Quit));
+        assert_eq!(map.game.action_for(Trigger::Key(Key::P)), None);
+        assert_eq!(map.stick, StickSettings { sensitivity: 1.5, ..StickSettings::default() });
+    }
+
+    #[test]
+    fn bundled_bindings_file_matches_default() {
+        assert_eq!(InputMap::parse(include_str!("../controls.cfg")), Ok(InputMap::default()));
+    }
+
+    #[test]
+    fn rejects_invalid_bindings() {
+        assert_eq!(parse_error("[left]\nmove_up = Foo"), "line 2: unknown input `Foo`");
+        assert_eq!(parse_error("[left]\njump = W"), "line 2: unknown action `jump`");
+        assert_eq!(parse_error("[middle]"), "line 1: unknown section `[middle]`, expected `[left]`, `[right]`, `[game]`, `[replay]` or `[gamepad]`");
+        assert_eq!(parse_error("move_up = W"), "line 1: bindings must come after a section header such as `[left]`");
+        assert_eq!(parse_error("[left]\nmove_up W"), "line 2: expected `action = input`, found `move_up W`");
+        assert_eq!(parse_error("[left]\nmove_up ="), "line 2: nothing is bound to `move_up`");
+        assert_eq!(parse_error("[left]\nquit = Escape"), "line 2: `quit` belongs in the `[game]` section");
+        assert_eq!(parse_error("[game]\nserve = Space"), "line 2: `serve` belongs in a `[left]` or `[right]` section");
+        assert_eq!(parse_error("[game]\nfaster = X"), "line 2: `faster` belongs in the `[replay]` section");
+        assert_eq!(parse_error("[replay]\npause = P"), "line 2: `pause` belongs in the `[game]` section");
+        assert_eq!(parse_error("[left]\nmove_to = W"), "line 2: `move_to` must be bound to an axis such as `PointerY`, not `W`");
+        assert_eq!(parse_error("[left]\nmove_up = PointerY"), "line 2: `move_up` must be bound to a key or mouse button, not `PointerY`");
+        assert_eq!(parse_error("[left]\nmove_up = W\n[right]\nmove_down = w"), "line 4: `w` is already bound on line 2");
+        assert_eq!(parse_error("[left]\nmove_up = W"), "nothing is bound to `quit`");
+    }
+
+    #[test]
+    fn rejects_invalid_gamepad_settings() {
+        assert_eq!(parse_error("[gamepad]\nrumble = 1"), "line 2: unknown gamepad setting `rumble`");
+        assert_eq!(parse_error("[gamepad]\ndeadzone = lots"), "line 2: `deadzone` must be a number, not `lots`");
+        assert_eq!(parse_error("[gamepad]\ndeadzone = 1"), "line 2: `deadzone` must be at least 0 and less than 1");
+        assert_eq!(parse_error("[gamepad]\nsensitivity = 0"), "line 2: `sensitivity` must be greater than 0");
+    }
+}
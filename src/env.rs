@@ -0,0 +1,327 @@
+//! A reinforcement learning environment in the style of OpenAI Gym.
+//!
+//! An agent plays one paddle against the AI. Each `step` repeats the agent's move for
+//! `frame_skip` ticks, then returns what the agent can see, the reward it earned, and whether
+//! the episode is over. Every episode is seeded from the environment's own seed, so the same
+//! seed and moves always give the same observations and rewards.
+
+use rand::Rng;
+use render;
+use software_renderer::SoftwareRenderer;
+use {AiController, AiSettings, Difficulty, GameRng, Input, PaddleInput, Side, Simulation};
+use {GAME_HEIGHT, GAME_WIDTH};
+
+/// The number of values in a state observation.
+pub const STATE_SIZE: usize = 7;
+
+// Ball velocities are divided by this to keep state values roughly between -1 and 1.
+const VELOCITY_SCALE: f32 = 1000.0;
+
+/// The kind of observation that the environment returns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObservationKind {
+    /// A vector of `STATE_SIZE` numbers describing the match; see `Env::state`.
+    State,
+    /// The match drawn into a `width` by `height` image.
+    Pixels { width: u32, height: u32 },
+}
+
+/// What the agent sees after a reset or step.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Observation {
+    State(Vec<f32>),
+    /// RGBA pixels, four bytes each, row by row from the top left.
+    Pixels(Vec<u8>),
+}
+
+/// How the agent moves its paddle during a step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Move {
+    Stay,
+    Up,
+    Down,
+    /// Moves at a fraction of full speed, from -1 for up to 1 for down.
+    Axis(f32),
+}
+
+/// The reward for each kind of event during a step.
+/// Returning the ball is worth nothing by default, so only points count.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rewards {
+    pub point_won: f32,
+    pub point_lost: f32,
+    pub ball_returned: f32,
+}
+
+impl Default for Rewards {
+    fn default() -> Rewards {
+        Rewards {
+            point_won: 1.0,
+            point_lost: -1.0,
+            ball_returned: 0.0,
+        }
+    }
+}
+
+/// Settings for an environment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvConfig {
+    pub observation: ObservationKind,
+    /// The number of ticks that each step lasts, with the same move repeated.
+    pub frame_skip: u32,
+    /// Ticks per second of game time.
+    pub tick_rate: f32,
+    /// The paddle that the agent plays.
+    pub side: Side,
+    /// How well the other paddle plays, or `None` to leave it standing still.
+    pub opponent: Option<AiSettings>,
+    pub rewards: Rewards,
+    /// Ends episodes after this many steps, even if nobody has won.
+    pub max_steps: Option<u32>,
+}
+
+impl Default for EnvConfig {
+    fn default() -> EnvConfig {
+        EnvConfig {
+            observation: ObservationKind::State,
+            frame_skip: 4,
+            tick_rate: 60.0,
+            side: Side::Left,
+            opponent: Some(Difficulty::Medium.settings()),
+            rewards: Rewards::default(),
+            max_steps: None,
+        }
+    }
+}
+
+/// A match of Pong that an agent plays one step at a time.
+#[derive(Clone, Debug)]
+pub struct Env {
+    config: EnvConfig,
+    rng: GameRng,
+    simulation: Simulation,
+    opponent: Option<AiController>,
+    steps: u32,
+}
+
+impl Env {
+    /// Creates an environment whose episodes are all seeded from `seed`.
+    /// Call `reset` to get the first observation.
+    pub fn new(config: EnvConfig, seed: u64) -> Env {
+        let mut env = Env {
+            config,
+            rng: GameRng::from_seed(seed),
+            simulation: Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, seed),
+            opponent: None,
+            steps: 0,
+        };
+        env.reset();
+        env
+    }
+
+    /// Starts a new match, returning the first observation.
+    pub fn reset(&mut self) -> Observation {
+        let (width, height) = (GAME_WIDTH as f32, GAME_HEIGHT as f32);
+        self.simulation = Simulation::with_seed(width, height, self.rng.next_u64());
+        let side = self.config.side.opposite();
+        let seed = self.rng.next_u64();
+        self.opponent = self.config.opponent.map(|settings| AiController::with_rng(side, settings, GameRng::from_seed(seed)));
+        self.steps = 0;
+        self.observe()
+    }
+
+    /// Plays the move for `frame_skip` ticks, or until the match is won.
+    /// Returns the new observation, the reward earned, and whether the episode is over.
+    pub fn step(&mut self, action: Move) -> (Observation, f32, bool) {
+        let agent = match action {
+            Move::Stay => PaddleInput::default(),
+            Move::Up => PaddleInput { up: true, ..PaddleInput::default() },
+            Move::Down => PaddleInput { down: true, ..PaddleInput::default() },
+            Move::Axis(axis) => PaddleInput { axis, ..PaddleInput::default() },
+        };
+        let params = self.simulation.update_params(1.0 / self.config.tick_rate);
+        let side = self.config.side;
+        let rewards = self.config.rewards;
+
+        let mut reward = 0.0;
+        for _ in 0..self.config.frame_skip.max(1) {
+            if self.simulation.winner().is_some() {
+                break;
+            }
+
+            let mut input = Input::default();
+            *input.paddle_mut(side) = agent;
+            if let Some(ref mut opponent) = self.opponent {
+                *input.paddle_mut(opponent.side()) = opponent.update(&self.simulation, params.dt);
+            }
+
+            let won = self.simulation.paddle(side).score;
+            let lost = self.simulation.paddle(side.opposite()).score;
+            let incoming = self.ball_incoming();
+            self.simulation.step(&params, &input);
+
+            let won = self.simulation.paddle(side).score - won;
+            let lost = self.simulation.paddle(side.opposite()).score - lost;
+            reward += won as f32 * rewards.point_won + lost as f32 * rewards.point_lost;
+            if won + lost == 0 && incoming && !self.ball_incoming() {
+                reward += rewards.ball_returned;
+            }
+        }
+
+        self.steps += 1;
+        let done = self.simulation.winner().is_some() || self.config.max_steps.is_some_and(|max| self.steps >= max);
+        (self.observe(), reward, done)
+    }
+
+    /// The match being played.
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    /// Describes the match from the agent's point of view, as if it were always playing on the
+    /// left: the ball's center x and y, its x and y velocity, the centers of the agent's and
+    /// the opponent's paddles, and the seconds left before the ball is served.
+    /// Positions are fractions of the court's size.
+    pub fn state(&self) -> Vec<f32> {
+        let sim = &self.simulation;
+        let (width, height) = (sim.width(), sim.height());
+        let ball = sim.ball().bounds();
+        let (vx, vy) = sim.ball().velocity();
+        let (x, vx) = match self.config.side {
+            Side::Left => ((ball.x + ball.width / 2.0) / width, vx),
+            Side::Right => (1.0 - (ball.x + ball.width / 2.0) / width, -vx),
+        };
+        let center_y = |side: Side| {
+            let paddle = sim.paddle(side).bounds;
+            (paddle.y + paddle.height / 2.0) / height
+        };
+        vec![
+            x,
+            (ball.y + ball.height / 2.0) / height,
+            vx / VELOCITY_SCALE,
+            vy / VELOCITY_SCALE,
+            center_y(self.config.side),
+            center_y(self.config.side.opposite()),
+            sim.ball().start_timer().max(0.0),
+        ]
+    }
+
+    fn observe(&self) -> Observation {
+        match self.config.observation {
+            ObservationKind::State => Observation::State(self.state()),
+            ObservationKind::Pixels { width, height } => {
+                let sim = &self.simulation;
+                let mut renderer = SoftwareRenderer::new(width, height, sim.width(), sim.height());
+                render::draw_game(&mut renderer, sim, 1.0).unwrap_or_else(|e| match e {});
+                Observation::Pixels(renderer.pixels().to_vec())
+            }
+        }
+    }
+
+    /// Whether the ball is heading towards the agent.
+    fn ball_incoming(&self) -> bool {
+        let vx = self.simulation.ball().velocity().0;
+        match self.config.side {
+            Side::Left => vx < 0.0,
+            Side::Right => vx > 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(env: &mut Env, moves: &[Move]) -> Vec<(Observation, f32, bool)> {
+        moves.iter().map(|&action| env.step(action)).collect()
+    }
+
+    fn moves(count: usize) -> Vec<Move> {
+        (0..count).map(|i| [Move::Up, Move::Stay, Move::Down, Move::Axis(0.5)][i / 10 % 4]).collect()
+    }
+
+    #[test]
+    fn observations_have_fixed_size() {
+        let mut env = Env::new(EnvConfig::default(), 1);
+        match env.reset() {
+            Observation::State(state) => assert_eq!(state.len(), STATE_SIZE),
+            other => panic!("{:?}", other),
+        }
+
+        let observation = ObservationKind::Pixels { width: 64, height: 36 };
+        let mut env = Env::new(EnvConfig { observation, ..EnvConfig::default() }, 1);
+        match env.step(Move::Stay).0 {
+            Observation::Pixels(pixels) => assert_eq!(pixels.len(), 64 * 36 * 4),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn state_is_seen_from_the_agent_side() {
+        let left = Env::new(EnvConfig::default(), 3).state();
+        let right = Env::new(EnvConfig { side: Side::Right, ..EnvConfig::default() }, 3).state();
+        assert_eq!(left[0], 0.5);
+        assert_eq!(left[2], -right[2]);
+        assert_eq!(left[4], right[5]);
+    }
+
+    #[test]
+    fn same_seed_replays_exactly() {
+        let moves = moves(1000);
+        let first = play(&mut Env::new(EnvConfig::default(), 7), &moves);
+        let second = play(&mut Env::new(EnvConfig::default(), 7), &moves);
+        assert!(first == second);
+
+        let other = play(&mut Env::new(EnvConfig::default(), 8), &moves);
+        assert!(first != other);
+    }
+
+    #[test]
+    fn frame_skip_repeats_ticks() {
+        let mut env = Env::new(EnvConfig { frame_skip: 6, ..EnvConfig::default() }, 1);
+        let countdown = env.simulation().ball().start_timer();
+        env.step(Move::Stay);
+        let elapsed = countdown - env.simulation().ball().start_timer();
+        assert!((elapsed - 0.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rewards_follow_the_score() {
+        let config = EnvConfig { opponent: Some(Difficulty::Hard.settings()), ..EnvConfig::default() };
+        let mut env = Env::new(config, 11);
+        let mut total = 0.0;
+        let mut done = false;
+        for _ in 0..100_000 {
+            let (_, reward, finished) = env.step(Move::Stay);
+            total += reward;
+            done = finished;
+            if done {
+                break;
+            }
+        }
+        assert!(done);
+        let sim = env.simulation();
+        assert_eq!(total, sim.left_paddle().score as f32 - sim.right_paddle().score as f32);
+        assert_eq!(sim.winner(), Some(Side::Right));
+    }
+
+    #[test]
+    fn returning_the_ball_can_be_rewarded() {
+        let rewards = Rewards { point_won: 0.0, point_lost: 0.0, ball_returned: 1.0 };
+        let config = EnvConfig { frame_skip: 1, rewards, max_steps: Some(60 * 60), ..EnvConfig::default() };
+        let mut env = Env::new(config, 5);
+
+        // Let a strong AI play for the agent.
+        let mut agent = AiController::with_rng(Side::Left, Difficulty::Hard.settings(), GameRng::from_seed(5));
+        let mut returns = 0.0;
+        loop {
+            let axis = agent.update(env.simulation(), 1.0 / 60.0).axis;
+            let (_, reward, done) = env.step(Move::Axis(axis));
+            returns += reward;
+            if done {
+                break;
+            }
+        }
+        assert!(returns >= 5.0, "only {} returns", returns);
+    }
+}
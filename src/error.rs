@@ -4,12 +4,18 @@
 //! https://doc.rust-lang.org/book/error-handling.html
 
 use glium;
+use rusty_pong::bot::BotError;
+use rusty_pong::net::NetError;
+use rusty_pong::replay::ReplayError;
+use rusty_pong::ParseError;
 use std::error::Error as StdError;
 use std::fmt;
 use std::io;
 
 /// The custom error type for Rusty Pong.
-/// Wraps any IO or glium errors.
+/// Wraps any IO or glium errors, problems with the controls or replay files, misbehaving bots,
+/// and trouble playing online.
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Error {
     IoError(io::Error),
@@ -17,6 +23,11 @@ pub enum Error {
     SwapBuffersError(glium::SwapBuffersError),
     BufferCreationError(glium::vertex::BufferCreationError),
     DrawError(glium::DrawError),
+    /// The path of the controls file, and what is wrong with it.
+    ControlsError(String, ParseError),
+    BotError(BotError),
+    ReplayError(ReplayError),
+    NetError(NetError),
 }
 
 impl From<io::Error> for Error {
@@ -49,6 +60,24 @@ impl From<glium::DrawError> for Error {
     }
 }
 
+impl From<BotError> for Error {
+    fn from(err: BotError) -> Error {
+        Error::BotError(err)
+    }
+}
+
+impl From<ReplayError> for Error {
+    fn from(err: ReplayError) -> Error {
+        Error::ReplayError(err)
+    }
+}
+
+impl From<NetError> for Error {
+    fn from(err: NetError) -> Error {
+        Error::NetError(err)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -57,28 +86,26 @@ impl fmt::Display for Error {
             Error::SwapBuffersError(ref err) => err.fmt(f),
             Error::BufferCreationError(ref err) => err.fmt(f),
             Error::DrawError(ref err) => err.fmt(f),
+            Error::ControlsError(ref path, ref err) => write!(f, "invalid controls in {}: {}", path, err),
+            Error::BotError(ref err) => err.fmt(f),
+            Error::ReplayError(ref err) => err.fmt(f),
+            Error::NetError(ref err) => err.fmt(f),
         }
     }
 }
 
 impl StdError for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::IoError(ref err) => err.description(),
-            Error::ShaderProgramError(ref err) => err.description(),
-            Error::SwapBuffersError(ref err) => err.description(),
-            Error::BufferCreationError(ref err) => err.description(),
-            Error::DrawError(ref err) => err.description(),
-        }
-    }
-
-    fn cause(&self) -> Option<&StdError> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
             Error::IoError(ref err) => Some(err),
             Error::ShaderProgramError(ref err) => Some(err),
             Error::SwapBuffersError(ref err) => Some(err),
             Error::BufferCreationError(ref err) => Some(err),
             Error::DrawError(ref err) => Some(err),
+            Error::ControlsError(_, ref err) => Some(err),
+            Error::BotError(ref err) => Some(err),
+            Error::ReplayError(ref err) => Some(err),
+            Error::NetError(ref err) => Some(err),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,157 @@
+//! Gamepads and other devices that can drive a paddle with an analog stick or a d-pad.
+//!
+//! Frontends wrap each physical controller in an `InputDevice`, and `StickSettings` turns its
+//! state into a `PaddleInput`. `ScriptedDevice` plays back a fixed sequence of states so that
+//! the analog controls can be tested without any hardware.
+
+use PaddleInput;
+
+const DEFAULT_DEADZONE: f32 = 0.15;
+const DEFAULT_SENSITIVITY: f32 = 1.0;
+
+/// The controls on a gamepad that matter to the game.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadState {
+    /// The vertical position of the stick, from -1 at the top to 1 at the bottom.
+    pub stick_y: f32,
+    pub dpad_up: bool,
+    pub dpad_down: bool,
+    pub serve: bool,
+    pub pause: bool,
+}
+
+/// A controller that can be polled for its current state.
+pub trait InputDevice {
+    /// Returns the state of the controls as of now. Called once per frame.
+    fn poll(&mut self) -> GamepadState;
+}
+
+/// How an analog stick's position turns into paddle movement.
+/// Positions within `deadzone` of the center are ignored, so that a stick which doesn't quite
+/// return to the middle doesn't make the paddle drift. The rest of the range is scaled up by
+/// `sensitivity`, so values above 1 reach full speed before the stick is pushed all the way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StickSettings {
+    pub deadzone: f32,
+    pub sensitivity: f32,
+}
+
+impl Default for StickSettings {
+    fn default() -> StickSettings {
+        StickSettings {
+            deadzone: DEFAULT_DEADZONE,
+            sensitivity: DEFAULT_SENSITIVITY,
+        }
+    }
+}
+
+impl StickSettings {
+    /// Converts a stick position to a fraction of the paddle's full speed, between -1 and 1.
+    pub fn apply(&self, value: f32) -> f32 {
+        let magnitude = value.abs().min(1.0);
+        if magnitude <= self.deadzone {
+            return 0.0;
+        }
+        // Start from zero at the edge of the deadzone rather than jumping straight to it.
+        let scaled = (magnitude - self.deadzone) / (1.0 - self.deadzone) * self.sensitivity;
+        scaled.min(1.0).copysign(value)
+    }
+
+    /// Converts the state of a gamepad to the controls for a paddle.
+    /// The d-pad acts like the movement keys and overrides the stick.
+    pub fn paddle_input(&self, state: &GamepadState) -> PaddleInput {
+        PaddleInput {
+            up: state.dpad_up,
+            down: state.dpad_down,
+            axis: self.apply(state.stick_y),
+            serve: state.serve,
+            ..PaddleInput::default()
+        }
+    }
+}
+
+/// A device that reports a fixed sequence of states, one per poll.
+/// Once the sequence runs out, the last state is held.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedDevice {
+    states: Vec<GamepadState>,
+    next: usize,
+}
+
+impl ScriptedDevice {
+    pub fn new(states: Vec<GamepadState>) -> ScriptedDevice {
+        ScriptedDevice { states, next: 0 }
+    }
+}
+
+impl InputDevice for ScriptedDevice {
+    fn poll(&mut self) -> GamepadState {
+        let state = match self.states.get(self.next) {
+            Some(&state) => state,
+            None => self.states.last().cloned().unwrap_or_default(),
+        };
+        self.next += 1;
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Paddle, UpdateParams};
+
+    fn stick(stick_y: f32) -> GamepadState {
+        GamepadState { stick_y, ..GamepadState::default() }
+    }
+
+    #[test]
+    fn ignores_stick_inside_deadzone() {
+        let settings = StickSettings { deadzone: 0.5, sensitivity: 1.0 };
+        assert_eq!(settings.apply(0.4), 0.0);
+        assert_eq!(settings.apply(-0.5), 0.0);
+        assert_eq!(settings.apply(0.75), 0.5);
+        assert_eq!(settings.apply(-1.0), -1.0);
+    }
+
+    #[test]
+    fn sensitivity_reaches_full_speed_sooner() {
+        let settings = StickSettings { deadzone: 0.0, sensitivity: 2.0 };
+        assert_eq!(settings.apply(0.25), 0.5);
+        assert_eq!(settings.apply(-0.5), -1.0);
+        assert_eq!(settings.apply(0.9), 1.0);
+    }
+
+    #[test]
+    fn scripted_device_holds_last_state() {
+        let mut device = ScriptedDevice::new(vec![stick(0.5), stick(-1.0)]);
+        assert_eq!(device.poll(), stick(0.5));
+        assert_eq!(device.poll(), stick(-1.0));
+        assert_eq!(device.poll(), stick(-1.0));
+        assert_eq!(ScriptedDevice::default().poll(), GamepadState::default());
+    }
+
+    #[test]
+    fn stick_moves_paddle() {
+        let params = UpdateParams { dt: 0.1, game_width: 1280.0, game_height: 720.0 };
+        let settings = StickSettings { deadzone: 0.2, sensitivity: 1.0 };
+        let mut device = ScriptedDevice::new(vec![
+            stick(1.0),
+            stick(0.1),
+            stick(-0.6),
+            GamepadState { stick_y: 1.0, dpad_up: true, ..GamepadState::default() },
+        ]);
+        let mut paddle = Paddle::new(25.0, 360.0);
+        let mut speeds = Vec::new();
+        for _ in 0..4 {
+            paddle.update(&params, &settings.paddle_input(&device.poll()));
+            speeds.push(paddle.vy);
+        }
+
+        // Full speed down, nothing from a resting stick, half speed up, then the d-pad wins.
+        let full_speed = speeds[0];
+        assert!(full_speed > 0.0);
+        assert_eq!(speeds[1], 0.0);
+        assert!((speeds[2] + full_speed / 2.0).abs() < 0.01);
+        assert_eq!(speeds[3], -full_speed);
+    }
+}
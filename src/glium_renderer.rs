@@ -0,0 +1,130 @@
+//! Draws the game with OpenGL using glium.
+
+use error::Error;
+use glium::{self, Frame, Program, Surface, VertexBuffer};
+use glium::backend::Facade;
+use rusty_pong::Rectangle;
+use rusty_pong::render::{Color, Renderer};
+use Result;
+
+/// Draws onto a glium frame, scaling the game to fit the window and letterboxing as needed.
+pub struct GliumRenderer<'a> {
+    frame: &'a mut Frame,
+    shader_program: &'a Program,
+    rect_vertex_buffer: &'a VertexBuffer<Vertex>,
+    game_width: f32,
+    game_height: f32,
+}
+
+impl<'a> GliumRenderer<'a> {
+    /// Creates a renderer that draws a game of the given size onto `frame`.
+    pub fn new(
+        frame: &'a mut Frame,
+        shader_program: &'a Program,
+        rect_vertex_buffer: &'a VertexBuffer<Vertex>,
+        game_width: f32,
+        game_height: f32,
+    ) -> GliumRenderer<'a> {
+        GliumRenderer {
+            frame,
+            shader_program,
+            rect_vertex_buffer,
+            game_width,
+            game_height,
+        }
+    }
+}
+
+impl<'a> Renderer for GliumRenderer<'a> {
+    type Error = Error;
+
+    fn clear(&mut self, color: Color) -> Result<()> {
+        self.frame.clear_color(color[0], color[1], color[2], color[3]);
+        Ok(())
+    }
+
+    /// Draws a rectangle onto the frame buffer.
+    /// x and y are in game coordinates (1280x720) with (0, 0) at the top left of the frame.
+    fn draw_rectangle(&mut self, rect: Rectangle, color: Color) -> Result<()> {
+        let (frame_width, frame_height) = (self.frame.get_dimensions().0 as f32, self.frame.get_dimensions().1 as f32);
+
+        // Matrix to transform the 1x1 square at (0, 0) into a (width, height) square at (x, y).
+        let transform: [[f32; 4]; 4] = [
+            [rect.width, 0.0, 0.0, 0.0],
+            [0.0, rect.height, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [rect.x, rect.y, 0.0, 1.0],
+        ];
+
+        // Matrix to project square from 2D screen coordinates into OpenGL device coordinates.
+        let scale = f32::min(frame_width  / self.game_width, frame_height / self.game_height);
+        let shift_x = 1.0 - self.game_width * scale / frame_width;
+        let shift_y = self.game_height * scale / frame_height - 1.0;
+        let projection: [[f32; 4]; 4] = [
+            [2.0 * scale / frame_width, 0.0, 0.0, 0.0],
+            [0.0, -2.0 * scale / frame_height, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-1.0 + shift_x, 1.0 + shift_y, 0.0, 1.0],
+        ];
+
+        // Render the quad using the calculated transform.
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        Ok(
+            self.frame.draw(
+                self.rect_vertex_buffer,
+                indices,
+                self.shader_program,
+                &uniform! { color: color, transform: transform, projection: projection },
+                &Default::default())?
+        )
+    }
+}
+
+/// Load and compile the shaders from the source files.
+/// The shader renders solidly filled polygons.
+pub fn create_shader_program<F: Facade>(display: &F) -> Result<Program> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut vertex_shader_file = File::open("shaders/vertex.glsl")?;
+    let mut vertex_shader_src = String::new();
+    vertex_shader_file.read_to_string(&mut vertex_shader_src)?;
+
+    let mut fragment_shader_file = File::open("shaders/fragment.glsl")?;
+    let mut fragment_shader_src = String::new();
+    fragment_shader_file.read_to_string(&mut fragment_shader_src)?;
+
+    let program = Program::from_source(display, &vertex_shader_src, &fragment_shader_src, None)?;
+
+    Ok(program)
+}
+
+/// Creates the vertex buffer for a unit square.
+pub fn create_rect_vertex_buffer<F: Facade>(display: &F) -> Result<VertexBuffer<Vertex>> {
+    let rect_vertices = vec![
+        Vertex { position: [0.0, 0.0] },
+        Vertex { position: [0.0, 1.0] },
+        Vertex { position: [1.0, 1.0] },
+
+        Vertex { position: [0.0, 0.0] },
+        Vertex { position: [1.0, 1.0] },
+        Vertex { position: [1.0, 0.0] },
+    ];
+    Ok(VertexBuffer::new(display, &rect_vertices)?)
+}
+
+/// The per-vertex data for our triangles.
+/// `postion` is the only vertex attribute because we are only rendering solidly filled
+/// 2d polygons.
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex {
+    position: [f32; 2],
+}
+// This is a magic glium macro to implement the required `Vertex` trait
+// for our vertex structure. This trait builds the vertex format information
+// required by glium and OpenGL.
+//
+// For more information, see:
+// https://tomaka.github.io/glium/glium/macro.implement_vertex!.html
+// https://tomaka.github.io/glium/glium/vertex/trait.Vertex.html
+implement_vertex!(Vertex, position);
@@ -0,0 +1,114 @@
+//! Reads gamepads through the Linux joystick interface at `/dev/input/js*`.
+//!
+//! The driver numbers the buttons and axes itself. This uses the layout that the xpad driver
+//! gives Xbox-style controllers, which most other drivers follow for the left stick, the d-pad,
+//! and the bottom face button.
+
+use libc;
+use rusty_pong::{GamepadState, InputDevice};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+
+const MAX_JOYSTICKS: u32 = 4;
+
+// Each event is a 32-bit timestamp, a 16-bit value, a type, and the number of the button or axis.
+const EVENT_SIZE: usize = 8;
+const EVENT_BUTTON: u8 = 0x01;
+const EVENT_AXIS: u8 = 0x02;
+// Set on the events sent when the device is opened, which report its starting state.
+const EVENT_INIT: u8 = 0x80;
+
+const AXIS_LEFT_STICK_Y: u8 = 1;
+const AXIS_DPAD_Y: u8 = 7;
+const BUTTON_SOUTH: u8 = 0;
+const BUTTON_START: u8 = 7;
+
+/// A gamepad opened through the joystick interface.
+pub struct Joystick {
+    file: Option<File>,
+    state: GamepadState,
+}
+
+impl Joystick {
+    /// Opens every joystick that is connected, in the order they were connected.
+    pub fn open_all() -> Vec<Joystick> {
+        (0..MAX_JOYSTICKS)
+            .filter_map(|i| Joystick::open(&format!("/dev/input/js{}", i)).ok())
+            .collect()
+    }
+
+    /// Opens a joystick device without blocking on reads.
+    pub fn open(path: &str) -> io::Result<Joystick> {
+        let file = OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path)?;
+        Ok(Joystick {
+            file: Some(file),
+            state: GamepadState::default(),
+        })
+    }
+}
+
+impl InputDevice for Joystick {
+    fn poll(&mut self) -> GamepadState {
+        let mut buffer = [0; EVENT_SIZE * 32];
+        while let Some(ref mut file) = self.file {
+            match file.read(&mut buffer) {
+                // The device only ever returns whole events.
+                Ok(count) if count > 0 => {
+                    for event in buffer[..count].chunks_exact(EVENT_SIZE) {
+                        apply_event(&mut self.state, event);
+                    }
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                // The gamepad was unplugged, so let go of everything.
+                _ => {
+                    self.file = None;
+                    self.state = GamepadState::default();
+                }
+            }
+        }
+        self.state
+    }
+}
+
+/// Updates the state of a gamepad with an event read from the joystick device.
+fn apply_event(state: &mut GamepadState, event: &[u8]) {
+    let value = i16::from_ne_bytes([event[4], event[5]]);
+    match (event[6] & !EVENT_INIT, event[7]) {
+        (EVENT_AXIS, AXIS_LEFT_STICK_Y) => state.stick_y = f32::from(value) / f32::from(i16::MAX),
+        (EVENT_AXIS, AXIS_DPAD_Y) => {
+            state.dpad_up = value < 0;
+            state.dpad_down = value > 0;
+        }
+        (EVENT_BUTTON, BUTTON_SOUTH) => state.serve = value != 0,
+        (EVENT_BUTTON, BUTTON_START) => state.pause = value != 0,
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(value: i16, kind: u8, number: u8) -> [u8; EVENT_SIZE] {
+        let value = value.to_ne_bytes();
+        [0, 0, 0, 0, value[0], value[1], kind, number]
+    }
+
+    #[test]
+    fn applies_stick_dpad_and_buttons() {
+        let mut state = GamepadState::default();
+        apply_event(&mut state, &event(-i16::MAX, EVENT_AXIS | EVENT_INIT, AXIS_LEFT_STICK_Y));
+        assert_eq!(state.stick_y, -1.0);
+
+        apply_event(&mut state, &event(i16::MAX, EVENT_AXIS, AXIS_DPAD_Y));
+        apply_event(&mut state, &event(1, EVENT_BUTTON, BUTTON_START));
+        assert_eq!((state.dpad_up, state.dpad_down, state.pause), (false, true, true));
+
+        // Other axes and buttons are ignored.
+        apply_event(&mut state, &event(1, EVENT_BUTTON, 3));
+        apply_event(&mut state, &event(0, EVENT_AXIS, 0));
+        assert!(!state.serve);
+        assert_eq!(state.stick_y, -1.0);
+    }
+}
@@ -0,0 +1,48 @@
+//! Translates glutin key codes and mouse buttons into the ones understood by the simulation.
+
+use glium::glutin::{self, VirtualKeyCode};
+use rusty_pong::{Key, MouseButton};
+
+/// Converts a key code from glutin, returning `None` for keys that can't be bound.
+pub fn key_from_virtual(key: VirtualKeyCode) -> Option<Key> {
+    // Both enums use the same names for the keys they share.
+    macro_rules! convert {
+        ($($name:ident),*) => {
+            match key {
+                $(VirtualKeyCode::$name => Some(Key::$name),)*
+                _ => None,
+            }
+        }
+    }
+
+    convert!(
+        A, B, C, D, E, F, G, H, I, J, K, L, M,
+        N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        Up, Down, Left, Right,
+        Space, Return, Escape, Tab, Back,
+        LShift, RShift, LControl, RControl, LAlt, RAlt
+    )
+}
+
+/// Converts a mouse button from glutin, returning `None` for buttons that can't be bound.
+pub fn mouse_button_from_glutin(button: glutin::MouseButton) -> Option<MouseButton> {
+    match button {
+        glutin::MouseButton::Left => Some(MouseButton::Left),
+        glutin::MouseButton::Right => Some(MouseButton::Right),
+        glutin::MouseButton::Middle => Some(MouseButton::Middle),
+        glutin::MouseButton::Other(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_bindable_keys() {
+        assert_eq!(key_from_virtual(VirtualKeyCode::W), Some(Key::W));
+        assert_eq!(key_from_virtual(VirtualKeyCode::Down), Some(Key::Down));
+        assert_eq!(key_from_virtual(VirtualKeyCode::F1), None);
+    }
+}
@@ -0,0 +1,58 @@
+//! San Diego Rusty Pong
+//!
+//! The game simulation for Rusty Pong, independent of any window or renderer.
+//! A `Simulation` can be stepped directly with `UpdateParams` and an `Input` snapshot,
+//! which makes it possible to test, script and run matches headlessly.
+
+extern crate png;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+mod ai;
+mod ball;
+pub mod bot;
+pub mod client;
+mod controller;
+mod controls;
+pub mod env;
+mod gamepad;
+pub mod net;
+mod paddle;
+mod rectangle;
+mod rng;
+pub mod rollback;
+pub mod render;
+pub mod replay;
+pub mod server;
+mod simulation;
+mod state;
+pub mod software_renderer;
+mod timestep;
+pub mod tournament;
+
+pub use ai::{AiController, AiSettings, Difficulty};
+pub use ball::{Ball, ServeDirection, ServeSettings};
+pub use controller::{Controller, ControllerKind};
+pub use controls::{Action, Bindings, InputMap, InputState, Key, MouseButton, ParseError, Trigger};
+pub use gamepad::{GamepadState, InputDevice, ScriptedDevice, StickSettings};
+pub use paddle::{Paddle, PaddleInput, Side};
+pub use rectangle::{Rectangle, SweepHit};
+pub use rng::GameRng;
+pub use simulation::{Input, Simulation};
+pub use timestep::FixedTimestep;
+
+pub const GAME_WIDTH: u32 = 1280;
+pub const GAME_HEIGHT: u32 = 720;
+pub const SCORE_TO_WIN: u32 = 10;
+
+/// Information about the current game frame.
+/// Gets passed to each game object during update.
+#[derive(Clone, Debug)]
+pub struct UpdateParams {
+    pub dt: f32,              // The amount of time in seconds to advance the simulation.
+    pub game_width: f32,
+    pub game_height: f32,
+}
@@ -4,26 +4,121 @@
 
 #[macro_use]
 extern crate glium;
-extern crate rand;
+#[cfg(unix)]
+extern crate libc;
+extern crate rusty_pong;
 
-mod ball;
 mod error;
-mod paddle;
-mod rectangle;
-
-pub use paddle::Paddle;
-pub use ball::Ball;
-pub use rectangle::Rectangle;
-use glium::glutin::VirtualKeyCode;
-use std::collections::HashSet;
+mod glium_renderer;
+#[cfg(target_os = "linux")]
+mod joystick;
+mod keyboard;
+#[cfg(unix)]
+mod tui;
+
+use glium_renderer::{GliumRenderer, Vertex};
+use rusty_pong::client::Client;
+use rusty_pong::net::{Link, NetConditions};
+use rusty_pong::replay::{Replay, ReplayViewer};
+use rusty_pong::rollback::RollbackSession;
+use rusty_pong::server::Server;
+use rusty_pong::tournament::{self, TournamentConfig};
+use rusty_pong::{Action, Controller, ControllerKind, Difficulty, FixedTimestep, GameRng, GamepadState, Input};
+use rusty_pong::{InputDevice, InputMap, InputState, PaddleInput, Side, Simulation, Trigger, UpdateParams};
+use rusty_pong::{render, GAME_HEIGHT, GAME_WIDTH};
+use rusty_pong::software_renderer::SoftwareRenderer;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
 
 pub type Result<T> = std::result::Result<T, error::Error>;
 
 /// The entry point for the game.
 fn main() {
-    // Create and run the game.
-    // This will block until the game exits or an error occurs.
-    let result = Game::run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    // Options that can be given along with any mode that plays the game.
+    let controls_path = take_option(&mut args, "--controls");
+    let overlay = take_flag(&mut args, "--overlay");
+    let record_path = take_option(&mut args, "--record");
+    let ai = take_option(&mut args, "--ai");
+    let bot = take_option(&mut args, "--bot");
+
+    // Options for making online play seem to have a worse connection than it does.
+    let latency = take_option(&mut args, "--latency");
+    let loss = take_option(&mut args, "--loss");
+    let broadcast_delay = take_option(&mut args, "--broadcast-delay");
+
+    // Options for simulating matches.
+    let matches = take_option(&mut args, "--matches");
+    let seed = take_option(&mut args, "--seed");
+    let format = take_option(&mut args, "--format");
+
+    let result = match args[..] {
+        // Create and run the game.
+        // This will block until the game exits or an error occurs.
+        [] => load_controls(controls_path)
+            .and_then(|input_map| Game::run(input_map, choose_opponent(ai, bot)?, overlay, record_path)),
+
+        // Play back a recorded match.
+        ["--replay", path] => load_controls(controls_path).and_then(|input_map| Game::replay(input_map, path, overlay)),
+
+        // Play online, either hosting the match or joining one.
+        ["--host", port] => load_controls(controls_path).and_then(|input_map| {
+            let link = open_link(Some(port), latency, loss)?;
+            println!("Waiting for a player to join on port {}...", port);
+            let session = RollbackSession::host(link, GAME_TICK_RATE)?;
+            Game::online(input_map, Online::Peer(Box::new(session)), overlay)
+        }),
+        ["--join", address] => load_controls(controls_path).and_then(|input_map| {
+            let session = RollbackSession::join(open_link(None, latency, loss)?, address)?;
+            Game::online(input_map, Online::Peer(Box::new(session)), overlay)
+        }),
+
+        // Play or watch a match on a dedicated server.
+        ["--connect", address] => load_controls(controls_path).and_then(|input_map| {
+            let client = Client::connect(open_link(None, latency, loss)?, address, false)?;
+            Game::online(input_map, Online::Server(Box::new(client)), overlay)
+        }),
+        ["--spectate", address] => load_controls(controls_path).and_then(|input_map| {
+            let client = Client::connect(open_link(None, latency, loss)?, address, true)?;
+            Game::online(input_map, Online::Server(Box::new(client)), overlay)
+        }),
+
+        // Run a dedicated server without a window.
+        ["server", port] => open_link(Some(port), latency, loss).and_then(|link| {
+            let mut server = Server::new(link, GAME_TICK_RATE);
+            if let Some(delay) = broadcast_delay {
+                let delay = delay
+                    .parse()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f32(seconds).ok())
+                    .filter(|&delay| delay <= MAX_BROADCAST_DELAY);
+                server.set_spectator_delay(delay.unwrap_or_else(|| {
+                    print_usage();
+                    std::process::exit(2);
+                }));
+            }
+            println!("Serving matches on port {}", port);
+            Ok(server.run()?)
+        }),
+
+        // Play in the terminal instead of a window.
+        #[cfg(unix)]
+        ["--tui"] => load_controls(controls_path).and_then(|input_map| tui::run(input_map, choose_opponent(ai, bot)?)),
+
+        // Play matches between two controllers without a window and print the results.
+        ["simulate", left, right] => simulate(left, right, matches, seed, format),
+
+        // Draw the opening frame without opening a window.
+        ["--screenshot", path] => save_screenshot(path),
+
+        _ => {
+            print_usage();
+            std::process::exit(2);
+        }
+    };
 
     // If an error occurred, print out the error and exit with an error code.
     if let Err(error) = result {
@@ -33,10 +128,221 @@ fn main() {
     }
 }
 
-const GAME_WIDTH: u32 = 1280;
-const GAME_HEIGHT: u32 = 720;
-const GAME_FRAMERATE: f32 = 60.0;
-const SCORE_TO_WIN: u32 = 10;
+/// Prints the command line options to stderr.
+fn print_usage() {
+    eprintln!("Usage:");
+    eprintln!("  rusty-pong                      Play the game in a window");
+    if cfg!(unix) {
+        eprintln!("  rusty-pong --tui                Play the game in the terminal");
+    }
+    eprintln!("  rusty-pong --replay <file>      Play back a match recorded with --record");
+    eprintln!("                                  Step, seek and change speed with the [replay] controls");
+    eprintln!("  rusty-pong --host <port>        Wait for a player to join online, and play on the left");
+    eprintln!("  rusty-pong --join <address>     Join a match hosted at the address and port, playing on the right");
+    eprintln!("  rusty-pong --connect <address>  Play a match on a dedicated server");
+    eprintln!("  rusty-pong --spectate <address> Watch the match on a dedicated server");
+    eprintln!("  rusty-pong server <port>        Run a dedicated server without a window");
+    eprintln!("  rusty-pong --screenshot <file>  Save the opening frame as a PNG without a window");
+    eprintln!("  rusty-pong simulate <left> <right>");
+    eprintln!("                                  Play matches between two controllers without a window:");
+    eprintln!("                                  idle, easy, medium, hard, or bot:<command>");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --controls <file>               Read the controls from a bindings file instead of {}", CONTROLS_PATH);
+    eprintln!("  --ai <easy|medium|hard>         Play against the computer, which takes the right paddle");
+    eprintln!("  --bot <command>                 Play against a bot program on the right; see the bot module");
+    eprintln!("  --overlay                       Mark where the ball is predicted to reach a paddle");
+    eprintln!("  --record <file>                 Record the match in the window to a replay file");
+    eprintln!("  --latency <ms>                  Hold back each packet sent online, to try out a slow connection");
+    eprintln!("  --loss <percent>                Drop some of the packets sent online");
+    eprintln!("  --broadcast-delay <seconds>     How far behind the match a server keeps spectators, up to 300 (default 0)");
+    eprintln!("  --matches <count>               The number of matches to simulate (default 100)");
+    eprintln!("  --seed <number>                 The seed for simulated matches (default 0)");
+    eprintln!("  --format <json|csv>             How to print the results of simulated matches (default json)");
+}
+
+/// Removes a flag from the arguments, returning whether it was there.
+fn take_flag(args: &mut Vec<&str>, name: &str) -> bool {
+    let count = args.len();
+    args.retain(|&arg| arg != name);
+    args.len() != count
+}
+
+/// Removes an option and the value after it from the arguments, returning the value.
+/// Exits with the usage message if the value is missing.
+fn take_option<'a>(args: &mut Vec<&'a str>, name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|&arg| arg == name)?;
+    if i + 1 >= args.len() {
+        print_usage();
+        std::process::exit(2);
+    }
+    let value = args[i + 1];
+    args.drain(i..i + 2);
+    Some(value)
+}
+
+/// Creates the opponent for the right paddle from the `--ai` and `--bot` options.
+/// Exits with the usage message if the difficulty is unknown or both options are given.
+fn choose_opponent(ai: Option<&str>, bot: Option<&str>) -> Result<Option<Controller>> {
+    let kind = match (ai.map(Difficulty::from_name), bot) {
+        (None, None) => return Ok(None),
+        (Some(Some(difficulty)), None) => ControllerKind::Ai(difficulty),
+        (None, Some(command)) => ControllerKind::Bot(command.to_string()),
+        _ => {
+            print_usage();
+            std::process::exit(2);
+        }
+    };
+    Ok(Some(kind.create(Side::Right, GameRng::from_entropy())?))
+}
+
+/// Opens a link for online play on the port, or on any port if none is given, making the
+/// connection seem as slow and lossy as the options ask.
+/// Exits with the usage message if any of the options are invalid.
+fn open_link(port: Option<&str>, latency: Option<&str>, loss: Option<&str>) -> Result<Link> {
+    let usage = || -> ! {
+        print_usage();
+        std::process::exit(2);
+    };
+    let mut conditions = NetConditions::default();
+    if let Some(latency) = latency {
+        conditions.latency = Duration::from_millis(latency.parse().unwrap_or_else(|_| usage()));
+    }
+    if let Some(loss) = loss {
+        let percent: f32 = loss.parse().unwrap_or_else(|_| usage());
+        if !(0.0..=100.0).contains(&percent) {
+            usage();
+        }
+        conditions.loss = percent / 100.0;
+    }
+
+    let port: u16 = match port {
+        Some(port) => port.parse().unwrap_or_else(|_| usage()),
+        None => 0,
+    };
+    let mut link = Link::bind(("0.0.0.0", port))?;
+    link.set_conditions(conditions);
+    Ok(link)
+}
+
+/// Plays seeded matches between the named controllers and prints a report to stdout.
+/// Exits with the usage message if any of the options are invalid.
+fn simulate(left: &str, right: &str, matches: Option<&str>, seed: Option<&str>, format: Option<&str>) -> Result<()> {
+    let usage = || -> ! {
+        print_usage();
+        std::process::exit(2);
+    };
+    let left = ControllerKind::from_name(left).unwrap_or_else(|| usage());
+    let right = ControllerKind::from_name(right).unwrap_or_else(|| usage());
+    let mut config = TournamentConfig::new(left, right);
+    if let Some(matches) = matches {
+        config.matches = matches.parse().unwrap_or_else(|_| usage());
+    }
+    if let Some(seed) = seed {
+        config.seed = seed.parse().unwrap_or_else(|_| usage());
+    }
+    let csv = match format {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(_) => usage(),
+    };
+
+    let report = tournament::run(&config)?;
+    if csv {
+        print!("{}", report.to_csv());
+    } else {
+        println!("{}", report.to_json());
+    }
+    Ok(())
+}
+
+/// The bindings file that is used when it exists and no other file is given.
+const CONTROLS_PATH: &str = "controls.cfg";
+
+/// Loads and validates the controls from the given bindings file, or from `controls.cfg`
+/// if it exists. Without either, the default controls are used.
+fn load_controls(path: Option<&str>) -> Result<InputMap> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(CONTROLS_PATH).exists() => CONTROLS_PATH,
+        None => return Ok(InputMap::default()),
+    };
+    let text = fs::read_to_string(path)?;
+    InputMap::parse(&text).map_err(|err| error::Error::ControlsError(path.to_string(), err))
+}
+
+/// Draws the opening frame of a match using the software renderer and saves it as a PNG.
+fn save_screenshot(path: &str) -> Result<()> {
+    let (width, height) = (GAME_WIDTH as f32, GAME_HEIGHT as f32);
+    let simulation = Simulation::new(width, height);
+    let mut renderer = SoftwareRenderer::new(GAME_WIDTH, GAME_HEIGHT, width, height);
+    render::draw_game(&mut renderer, &simulation, 1.0).unwrap_or_else(|e| match e {});
+    Ok(renderer.save_png(path)?)
+}
+
+/// Opens the gamepads that are connected, on platforms that support them.
+fn open_gamepads() -> Vec<Box<dyn InputDevice>> {
+    #[cfg(target_os = "linux")]
+    let gamepads = joystick::Joystick::open_all()
+        .into_iter()
+        .map(|joystick| Box::new(joystick) as Box<dyn InputDevice>)
+        .collect();
+    #[cfg(not(target_os = "linux"))]
+    let gamepads = Vec::new();
+    gamepads
+}
+
+const GAME_TICK_RATE: f32 = 60.0;
+const GAME_MAX_TICKS_PER_FRAME: u32 = 10;
+const GAME_MAX_FRAMERATE: f32 = 240.0;
+
+// The server keeps the state after every tick of the delay, so it can't be too long.
+const MAX_BROADCAST_DELAY: Duration = Duration::from_secs(300);
+
+// The speeds a replay can be played at, the one it starts at, and how far seeking jumps.
+const REPLAY_SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const REPLAY_NORMAL_SPEED: usize = 2;
+const REPLAY_SEEK_SECONDS: f32 = 5.0;
+
+/// An online match, played either directly against another player or on a server.
+enum Online {
+    Peer(Box<RollbackSession>),
+    Server(Box<Client>),
+}
+
+impl Online {
+    fn set_input_map(&mut self, input_map: &InputMap) {
+        match *self {
+            Online::Peer(ref mut session) => session.set_input_map(input_map),
+            Online::Server(ref mut client) => client.set_input_map(input_map),
+        }
+    }
+
+    fn tick_rate(&self) -> f32 {
+        match *self {
+            Online::Peer(ref session) => session.tick_rate(),
+            Online::Server(ref client) => client.tick_rate(),
+        }
+    }
+
+    fn simulation(&self) -> &Simulation {
+        match *self {
+            Online::Peer(ref session) => session.simulation(),
+            Online::Server(ref client) => client.simulation(),
+        }
+    }
+
+    /// Plays the next tick with the input for this player's paddle.
+    fn advance(&mut self, input: PaddleInput) -> Result<()> {
+        match *self {
+            Online::Peer(ref mut session) => {
+                session.advance(input)?;
+            }
+            Online::Server(ref mut client) => client.update(input)?,
+        }
+        Ok(())
+    }
+}
 
 /// The controller for the game.
 pub struct Game {
@@ -46,32 +352,78 @@ pub struct Game {
 
     width: f32,
     height: f32,
-    frame_rate: f32,
+    tick_rate: f32,
+
+    // The game actions such as pausing, and the keys, buttons and pointer position that are
+    // currently held, with the pointer in game coordinates.
+    input_map: InputMap,
+    input_state: InputState,
+    paused: bool,
 
-    pressed_keys: HashSet<VirtualKeyCode>,
+    // The connected gamepads, and the state each was in last frame.
+    gamepads: Vec<(Box<dyn InputDevice>, GamepadState)>,
 
-    left_paddle: Paddle,
-    right_paddle: Paddle,
+    // The computer player or bot, if there is one.
+    opponent: Option<Controller>,
 
-    ball: Ball,
+    // The match being recorded, or the recording being played back along with the index of
+    // the speed it is playing at.
+    recording: Option<Replay>,
+    viewer: Option<ReplayViewer>,
+    replay_speed: usize,
+
+    // The online match, if playing one, which decides how the match plays out.
+    online: Option<Online>,
+
+    // Whether to draw debugging information over the game.
+    overlay: bool,
+
+    simulation: Simulation,
 }
 
 impl Game {
-    /// Creates and runs the game.
+    /// Creates and runs the game with the given controls, against the opponent if there is one,
+    /// recording the match to `record_path` if given.
     /// This functions runs until the game exits or an error occurs.
-    pub fn run() -> Result<()> {
-        let mut game = Game::new()?;
-        game.run_game_loop()?;
-        Ok(())
+    pub fn run(input_map: InputMap, opponent: Option<Controller>, overlay: bool, record_path: Option<&str>) -> Result<()> {
+        let simulation = Simulation::new(GAME_WIDTH as f32, GAME_HEIGHT as f32);
+        let mut game = Game::new(input_map, simulation, GAME_TICK_RATE, overlay)?;
+        game.opponent = opponent;
+        game.recording = record_path.and_then(|_| Replay::new(&game.simulation, game.tick_rate));
+        let result = game.run_game_loop();
+
+        // Save the recording even if the game failed, since that is when it is most useful.
+        if let (Some(path), Some(mut recording)) = (record_path, game.recording.take()) {
+            recording.finish(&game.simulation);
+            recording.save(path)?;
+        }
+        result
+    }
+
+    /// Plays back the replay file in a window until the player quits.
+    pub fn replay(input_map: InputMap, path: &str, overlay: bool) -> Result<()> {
+        let viewer = ReplayViewer::new(Replay::load(path)?);
+        let mut game = Game::new(input_map, viewer.start(), viewer.replay().tick_rate, overlay)?;
+        game.viewer = Some(viewer);
+        game.run_game_loop()
+    }
+
+    /// Plays an online match in a window until the player quits.
+    fn online(input_map: InputMap, mut online: Online, overlay: bool) -> Result<()> {
+        online.set_input_map(&input_map);
+        let mut game = Game::new(input_map, online.simulation().clone(), online.tick_rate(), overlay)?;
+        game.online = Some(online);
+        game.run_game_loop()
     }
 
     /// Initializes the game.
-    fn new() -> Result<Game> {
+    fn new(input_map: InputMap, mut simulation: Simulation, tick_rate: f32, overlay: bool) -> Result<Game> {
         // Create a window using glutin.
         use glium::DisplayBuild;
         let display = glium::glutin::WindowBuilder::new()
             .with_dimensions(GAME_WIDTH, GAME_HEIGHT)
             .with_title("San Diego Rusty Pong")
+            .with_vsync()
             .build_glium()
             .unwrap();
 
@@ -80,66 +432,54 @@ impl Game {
             window.set_cursor_state(glium::glutin::CursorState::Grab).unwrap_or(());
         }
 
-        // Load the shader for drawing rectangles.
-        let shader_program = Game::create_shader_program(&display)?;
-
-        // Create the vertex buffer for a unit square.
-        let rect_vertices = vec![
-            Vertex { position: [0.0, 0.0] },
-            Vertex { position: [0.0, 1.0] },
-            Vertex { position: [1.0, 1.0] },
-
-            Vertex { position: [0.0, 0.0] },
-            Vertex { position: [1.0, 1.0] },
-            Vertex { position: [1.0, 0.0] },
-        ];
-        let rect_vertex_buffer = glium::VertexBuffer::new(&display, &rect_vertices)?;
+        // Load the shader and vertex buffer for drawing rectangles.
+        let shader_program = glium_renderer::create_shader_program(&display)?;
+        let rect_vertex_buffer = glium_renderer::create_rect_vertex_buffer(&display)?;
 
         // Initialize all game objects.
-        let width = GAME_WIDTH as f32;
-        let height = GAME_HEIGHT as f32;
+        simulation.set_input_map(&input_map);
         Ok(Game {
-            display: display,
-            shader_program: shader_program,
-            rect_vertex_buffer: rect_vertex_buffer,
+            display,
+            shader_program,
+            rect_vertex_buffer,
 
-            width: width,
-            height: height,
-            frame_rate: GAME_FRAMERATE as f32,
+            width: simulation.width(),
+            height: simulation.height(),
+            tick_rate,
 
-            pressed_keys: HashSet::new(),
+            input_map,
+            input_state: InputState::new(),
+            paused: false,
 
-            left_paddle: Paddle::new(25.0, height / 2.0),
-            right_paddle: Paddle::new(width - 25.0, height / 2.0),
-            
-            ball: Ball::new(width / 2.0, height / 2.0),
-        })
-    }
+            gamepads: open_gamepads().into_iter().map(|device| (device, GamepadState::default())).collect(),
 
-    /// Load and compile the shaders from the source files.
-    /// The shader renders solidly filled polygons.
-    fn create_shader_program(display: &glium::backend::glutin_backend::GlutinFacade) -> Result<glium::Program> {
-        use std::fs::File;
-        use std::io::Read;
+            opponent: None,
 
-        let mut vertex_shader_file = File::open("shaders/vertex.glsl")?;
-        let mut vertex_shader_src = String::new();
-        vertex_shader_file.read_to_string(&mut vertex_shader_src)?;
+            recording: None,
+            viewer: None,
+            replay_speed: REPLAY_NORMAL_SPEED,
 
-        let mut fragment_shader_file = File::open("shaders/fragment.glsl")?;
-        let mut fragment_shader_src = String::new();
-        fragment_shader_file.read_to_string(&mut fragment_shader_src)?;
+            online: None,
 
-        let program = glium::Program::from_source(display, &vertex_shader_src, &fragment_shader_src, None)?;
+            overlay,
 
-        Ok(program)
+            simulation,
+        })
     }
 
     /// The game loop.
-    /// Each iteration through the loop handles any window events, reads user input, 
+    /// Each iteration through the loop handles any window events, reads user input,
     /// updates the game state, and renders a frame.
+    /// The game state is updated in fixed-size ticks, as many as are needed to keep up with
+    /// real time, and frames are drawn in between as often as the display allows.
     /// This loop runs until the user requests an exit, or an error occurs.
     fn run_game_loop(&mut self) -> Result<()> {
+        use std::thread;
+        use std::time::Instant;
+
+        let mut timestep = FixedTimestep::new(self.tick_rate, GAME_MAX_TICKS_PER_FRAME);
+        let min_frame_time = Duration::from_millis((1000.0 / GAME_MAX_FRAMERATE) as u64);
+        let mut last_frame = Instant::now();
         loop {
             let exit = self.poll_events();
             if exit {
@@ -147,106 +487,175 @@ impl Game {
             }
 
             // Update the game state.
-            let frame_time = 1.0 / self.frame_rate;
-            let params = UpdateParams {
-                dt: frame_time,
-                game_width: self.width,
-                game_height: self.height,
-            };
-            self.update(&params);
-
-            // Draw the frame.
-            self.render()?;
-
-            // Sleep until the next frame.
-            use std::thread;
-            use std::time::Duration;
-            let sleep_time = Duration::from_millis((1000.0 * frame_time) as u64);
-            thread::sleep(sleep_time);
+            let now = Instant::now();
+            let speed = if self.viewer.is_some() { REPLAY_SPEEDS[self.replay_speed] } else { 1.0 };
+            let ticks = timestep.advance((now - last_frame).mul_f32(speed));
+            last_frame = now;
+            let params = self.simulation.update_params(timestep.step());
+            let mut input = self.simulation.read_input(&self.input_state);
+            self.poll_gamepads(&mut input);
+            if !self.paused {
+                for _ in 0..ticks {
+                    self.update(&params, input)?;
+                }
+            }
+
+            // Draw the frame, blending between the last two ticks.
+            self.render(timestep.alpha())?;
+
+            // Avoid spinning when vsync is unavailable.
+            let frame_time = last_frame.elapsed();
+            if frame_time < min_frame_time {
+                thread::sleep(min_frame_time - frame_time);
+            }
         }
 
         // Game finished successfully.
-        return Ok(());
+        Ok(())
     }
 
-    /// Updates the game state.
-    /// `dt` represents delta time, the amount of time that the game will be advanced.
-    fn update(&mut self, params: &UpdateParams) {
-        if !self.has_winner()
-        {
-            self.left_paddle.update(&params, &self.pressed_keys);
-            self.right_paddle.update(&params, &self.pressed_keys);
-            self.ball.update(&params, &mut self.left_paddle, &mut self.right_paddle);
+    /// Advances the match by one tick, using the input from the replay being played back, or
+    /// else from the players and the opponent.
+    fn update(&mut self, params: &UpdateParams, mut input: Input) -> Result<()> {
+        if let Some(ref mut viewer) = self.viewer {
+            // Once the replay is over, the last tick is left on screen.
+            if viewer.step(&mut self.simulation) && viewer.tick() == viewer.tick_count() {
+                Game::check_replay_end(viewer, &self.simulation);
+            }
+            return Ok(());
         }
-    }
 
-    /// The winner is the first player to reach 10 points.
-    fn has_winner(&mut self) -> bool {
-        self.left_paddle.score >= SCORE_TO_WIN || self.right_paddle.score >= SCORE_TO_WIN
-    }
+        if let Some(ref mut online) = self.online {
+            // Either set of controls moves this player's paddle, and the other player or the
+            // server decides the rest.
+            online.advance(input.left.merge(&input.right))?;
+            self.simulation.clone_from(online.simulation());
+            return Ok(());
+        }
 
-    /// Renders the current game state.
-    fn render(&mut self) -> Result<()> {
-        // Ask glium for the buffer to draw to.
-        use glium::Surface;
-        let mut frame = self.display.draw();
-        
-        // Clear the screen.
-        frame.clear_color(0.0, 0.0, 0.0, 0.0);
+        if let Some(ref mut opponent) = self.opponent {
+            // The opponent takes over its paddle, looking at the match afresh each tick.
+            *input.paddle_mut(opponent.side()) = opponent.update(&self.simulation, params.dt)?;
+        }
 
-        // Draw the various UI elements.
-        self.draw_net(&mut frame)?;
-        self.draw_score(&mut frame, self.left_paddle.score, self.width * 0.25, 10.0)?;
-        self.draw_score(&mut frame, self.right_paddle.score, self.width * 0.75, 10.0)?;
+        self.simulation.step(params, &input);
+        if let Some(ref mut recording) = self.recording {
+            recording.record(&input, &self.simulation);
+        }
+        Ok(())
+    }
 
-        // Draw the player paddles.
-        self.left_paddle.render(&self, &mut frame)?;
-        self.right_paddle.render(&self, &mut frame)?;
+    /// Warns if the replay being viewed has reached its end in a different state than when it
+    /// was recorded.
+    fn check_replay_end(viewer: &ReplayViewer, simulation: &Simulation) {
+        if !viewer.replay().matches(simulation) {
+            eprintln!("Warning: the replay played out differently than when it was recorded");
+        }
+    }
 
-        // Draw the ball.
-        self.ball.render(&self, &mut frame)?;
+    /// Performs a replay action, moving through the replay being viewed or changing its speed.
+    fn replay_action(&mut self, action: Action) {
+        let viewer = match self.viewer {
+            Some(ref mut viewer) => viewer,
+            None => return,
+        };
+        let seek_ticks = (REPLAY_SEEK_SECONDS * viewer.replay().tick_rate) as usize;
+        let target = match action {
+            Action::StepBack => {
+                self.paused = true;
+                viewer.tick().saturating_sub(1)
+            }
+            Action::StepForward => {
+                self.paused = true;
+                viewer.tick() + 1
+            }
+            Action::SeekBack => viewer.tick().saturating_sub(seek_ticks),
+            Action::SeekForward => viewer.tick() + seek_ticks,
+            Action::Slower => {
+                self.replay_speed = self.replay_speed.saturating_sub(1);
+                return;
+            }
+            Action::Faster => {
+                self.replay_speed = (self.replay_speed + 1).min(REPLAY_SPEEDS.len() - 1);
+                return;
+            }
+            _ => return,
+        };
 
-        // Finish drawing and present the buffer.
-        Ok(frame.finish()?)
+        let was_over = viewer.tick() == viewer.tick_count();
+        viewer.seek(&mut self.simulation, target);
+        if !was_over && viewer.tick() == viewer.tick_count() {
+            Game::check_replay_end(viewer, &self.simulation);
+        }
     }
 
-    /// Draws a dotted line in the middle of the screen.
-    fn draw_net(&self, frame: &mut glium::Frame) -> Result<()> {
-        const NET_WIDTH: f32 = 8.0;
-        const NET_SEGMENT_HEIGHT: f32 = 50.0;
-        let mut rect = Rectangle {
-            x: (self.width - NET_WIDTH) / 2.0,
-            y: 0.0,
-            width: NET_WIDTH,
-            height: NET_SEGMENT_HEIGHT
-        };
+    /// Pauses or unpauses the game, except online, where the other player would be left waiting.
+    fn toggle_pause(&mut self) {
+        if self.online.is_none() {
+            self.paused = !self.paused;
+        }
+    }
 
-        while rect.y < self.height {
-            self.draw_rectangle(frame, rect, [0.1, 0.1, 0.1, 1.0])?;
-            rect.y += NET_SEGMENT_HEIGHT * 1.5;
+    /// Adds the controls from each gamepad to the input for its paddle.
+    /// The first gamepad plays on the left and the second on the right.
+    fn poll_gamepads(&mut self, input: &mut Input) {
+        let mut pause = false;
+        for (gamepad, &side) in self.gamepads.iter_mut().zip(&[Side::Left, Side::Right]) {
+            let state = gamepad.0.poll();
+            pause ^= state.pause && !gamepad.1.pause;
+            gamepad.1 = state;
+
+            let paddle = input.paddle_mut(side);
+            *paddle = paddle.merge(&self.input_map.stick.paddle_input(&state));
+        }
+        if pause {
+            self.toggle_pause();
         }
-        Ok(())
     }
 
-    /// Draws a series of dots representing the score for a player.
-    fn draw_score(&self, frame: &mut glium::Frame, score: u32, x: f32, y: f32) -> Result<()> {
-        const ROW_LENGTH: u32 = 5;
-        let mut rect = Rectangle {
-            x: 0.0,
-            y: 0.0,
-            width: 5.0,
-            height: 5.0
+    /// Renders the current game state.
+    /// `alpha` is how far the frame is between the previous tick and the current one.
+    fn render(&mut self, alpha: f32) -> Result<()> {
+        // Ask glium for the buffer to draw to.
+        let mut frame = self.display.draw();
+
+        let result = {
+            let mut renderer = GliumRenderer::new(
+                &mut frame,
+                &self.shader_program,
+                &self.rect_vertex_buffer,
+                self.width,
+                self.height,
+            );
+            render::draw_game(&mut renderer, &self.simulation, alpha).and_then(|()| {
+                if self.overlay {
+                    render::draw_prediction(&mut renderer, &self.simulation, 1.0 / self.tick_rate)
+                } else {
+                    Ok(())
+                }
+            }).and_then(|()| match self.viewer {
+                Some(ref viewer) => {
+                    render::draw_replay_progress(&mut renderer, &self.simulation, viewer.tick(), viewer.tick_count())
+                }
+                None => Ok(()),
+            })
         };
-        // Draw winning score in red.
-        let color = if score < SCORE_TO_WIN { [0.2, 0.2, 0.2, 1.0] } else {[1.0, 0.2, 0.2, 1.0] };
-        for i in 0..score {
-            let column = (i % ROW_LENGTH) as f32;
-            let row = (i / ROW_LENGTH) as f32;
-            rect.x = x + 8.0 * column;
-            rect.y = y + 8.0 * row;
-            self.draw_rectangle(frame, rect, color)?;
-        }
-        Ok(())
+
+        // Finish drawing and present the buffer, even if drawing failed.
+        frame.finish()?;
+        result
+    }
+
+    /// Converts the height of the pointer in the window into game coordinates.
+    fn pointer_to_game_y(&self, x: f32, y: f32) -> f32 {
+        // The pointer is reported in points, which are bigger than pixels on HiDPI screens.
+        let (window_width, window_height) = self
+            .display
+            .get_window()
+            .and_then(|window| window.get_inner_size_points())
+            .unwrap_or_else(|| self.display.get_framebuffer_dimensions());
+        let letterbox = render::Letterbox::new(window_width as f32, window_height as f32, self.width, self.height);
+        letterbox.to_game(x, y).1
     }
 
     /// Handles any new window or UI events.
@@ -256,96 +665,68 @@ impl Game {
     fn poll_events(&mut self) -> bool {
         use glium::glutin::{ElementState, Event};
 
+        let mut pressed = Vec::new();
         for event in self.display.poll_events() {
             match event {
                 // Window closed by the user.
                 Event::Closed => return true,
-                
-                // User pressed Escape to close the game.
-                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Escape)) => {
-                    return true;
-                },
 
-                // Keyboard input.
+                // Keyboard input. Key repeats don't count as new presses.
                 Event::KeyboardInput(ElementState::Pressed, _, Some(key)) => {
-                    self.pressed_keys.insert(key);
+                    if let Some(key) = keyboard::key_from_virtual(key) {
+                        if self.input_state.keys.insert(key) {
+                            pressed.push(Trigger::Key(key));
+                        }
+                    }
                 },
 
                 Event::KeyboardInput(ElementState::Released, _, Some(key)) => {
-                    self.pressed_keys.remove(&key);
+                    if let Some(key) = keyboard::key_from_virtual(key) {
+                        self.input_state.keys.remove(&key);
+                    }
+                },
+
+                // Mouse and touch input.
+                Event::MouseInput(ElementState::Pressed, button) => {
+                    if let Some(button) = keyboard::mouse_button_from_glutin(button) {
+                        if self.input_state.mouse_buttons.insert(button) {
+                            pressed.push(Trigger::Mouse(button));
+                        }
+                    }
+                },
+
+                Event::MouseInput(ElementState::Released, button) => {
+                    if let Some(button) = keyboard::mouse_button_from_glutin(button) {
+                        self.input_state.mouse_buttons.remove(&button);
+                    }
                 },
 
-                // TODO: Handle mouse/touch events.
+                Event::MouseMoved(x, y) => {
+                    self.input_state.pointer_y = Some(self.pointer_to_game_y(x as f32, y as f32));
+                },
+
+                Event::Touch(touch) => {
+                    let (x, y) = touch.location;
+                    self.input_state.pointer_y = Some(self.pointer_to_game_y(x as f32, y as f32));
+                },
 
                 // Other events are unhandled.
                 _ => (),
             }
         }
 
-        false
-    }
+        // Perform the game actions bound to anything that was just pressed.
+        for trigger in pressed {
+            match self.input_map.game.action_for(trigger) {
+                Some(Action::Quit) => return true,
+                Some(Action::Pause) => self.toggle_pause(),
+                _ => (),
+            }
+            if let Some(action) = self.input_map.replay.action_for(trigger) {
+                self.replay_action(action);
+            }
+        }
 
-    /// Draws a rectangle onto the given frame buffer.
-    /// x and y are in game coordinates (1280x720) with (0, 0) at the top left of the frame.
-    pub fn draw_rectangle(&self, frame: &mut glium::Frame, rect: Rectangle, color: [f32; 4]) -> Result<()> {
-        use glium::Surface;
-        
-        let (frame_width, frame_height) = (frame.get_dimensions().0 as f32, frame.get_dimensions().1 as f32);
-
-        // Matrix to transform the 1x1 square at (0, 0) into a (width, height) square at (x, y).
-        let transform: [[f32; 4]; 4] = [
-            [rect.width, 0.0, 0.0, 0.0],
-            [0.0, rect.height, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [rect.x, rect.y, 0.0, 1.0],
-        ];
-
-        // Matrix to project square from 2D screen coordinates into OpenGL device coordinates.
-        let scale = f32::min(frame_width  / self.width, frame_height / self.height);
-        let shift_x = 1.0 - self.width * scale / frame_width;
-        let shift_y = self.height * scale / frame_height - 1.0;
-        let projection: [[f32; 4]; 4] = [
-            [2.0 * scale / frame_width, 0.0, 0.0, 0.0],
-            [0.0, -2.0 * scale / frame_height, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [-1.0 + shift_x, 1.0 + shift_y, 0.0, 1.0],
-        ];
-
-        // Render the quad using the calculated transform.
-        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
-        Ok(
-            frame.draw(
-                &self.rect_vertex_buffer,
-                &indices,
-                &self.shader_program,
-                &uniform! { color: color, transform: transform, projection: projection },
-                &Default::default())?
-        )
+        false
     }
 }
-
-/// Information about the current game frame.
-/// Gets passed to each game object during update.
-#[derive(Clone, Debug)]
-pub struct UpdateParams {
-    pub dt: f32,              // The amount of time in seconds to advance the simulation.
-    pub game_width: f32,
-    pub game_height: f32,
-}
-
-
-/// The per-vertex data for our triangles.
-/// `postion` is the only vertex attribute because we are only rendering solidly filled
-/// 2d polygons.
-#[derive(Clone, Copy, Debug)]
-struct Vertex {
-    position: [f32; 2],
-}
-// This is a magic glium macro to implement the required `Vertex` trait
-// for our vertex structure. This trait builds the vertex format information
-// required by glium and OpenGL.
-//
-// For more information, see:
-// https://tomaka.github.io/glium/glium/macro.implement_vertex!.html
-// https://tomaka.github.io/glium/glium/vertex/trait.Vertex.html
-implement_vertex!(Vertex, position);
@@ -0,0 +1,457 @@
+//! Sends packets between copies of the game over UDP.
+//!
+//! A `Link` can also pretend the network is worse than it is, holding packets back and dropping
+//! some of them as they're sent, so that online play can be tried out on one machine.
+//!
+//! # Packets
+//!
+//! Every packet starts with the bytes `RPNT` and a byte for its kind. Numbers are little-endian.
+//!
+//! - Hello (0): u32 protocol version. Sent by a player asking to join.
+//! - Welcome (1): u32 protocol version, u64 seed, f32 tick rate. Sent in answer to a hello.
+//! - Inputs (2): u32 ack, u32 start tick, u8 count, then that many paddle inputs. Each input is
+//!   a byte of flags (1 up, 2 down, 4 serve, 8 target y), the f32 axis, and the f32 target y
+//!   if its flag is set. `ack` is the number of the other side's ticks received so far.
+//! - Join (3): u32 protocol version, u8 1 to spectate or 0 to play. Sent to a server.
+//! - Accept (4): u32 protocol version, u8 role (0 left, 1 right, 2 spectator), f32 tick rate.
+//!   Sent by a server in answer to a join.
+//! - Snapshot (5): u64 match id, u32 tick, u32 ack, then the state of the match to the end of
+//!   the packet. The match id is the seed of the match, which tells clients when the server has
+//!   started a new one. `ack` is the number of the receiving player's inputs that the server has
+//!   played.
+//! - Delta (6): u64 match id, u32 tick, u32 base tick, then a delta from the state of the match
+//!   after the base tick to the end of the packet. Sent to spectators in place of snapshots once
+//!   they have said which tick they are up to, using the `ack` of their inputs packets.
+//!
+//! Packets that are too short, or don't start with the magic bytes, are ignored. So are inputs
+//! packets with an axis outside -1 to 1 or a target y that isn't a finite number, which would
+//! otherwise leave a paddle nowhere.
+
+use rand::Rng;
+use state;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use {GameRng, PaddleInput, Side};
+
+/// The version of the packets described above.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// The most inputs sent in one packet, which keeps packets well under the usual MTU.
+pub const MAX_INPUTS_PER_PACKET: usize = 64;
+
+/// How long to go without hearing from the other side before giving up on it.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often to ask again while waiting for an answer, such as to a request to join.
+pub const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many of the most recent states of the match are kept to make and read deltas against.
+pub const DELTA_HISTORY: usize = 64;
+
+/// How long to sleep between checks for packets while waiting for them.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+const MAGIC: &[u8; 4] = b"RPNT";
+const KIND_HELLO: u8 = 0;
+const KIND_WELCOME: u8 = 1;
+const KIND_INPUTS: u8 = 2;
+const KIND_JOIN: u8 = 3;
+const KIND_ACCEPT: u8 = 4;
+const KIND_SNAPSHOT: u8 = 5;
+const KIND_DELTA: u8 = 6;
+
+const ROLE_LEFT: u8 = 0;
+const ROLE_RIGHT: u8 = 1;
+const ROLE_SPECTATOR: u8 = 2;
+
+const FLAG_UP: u8 = 1;
+const FLAG_DOWN: u8 = 2;
+const FLAG_SERVE: u8 = 4;
+const FLAG_TARGET_Y: u8 = 8;
+
+// Big enough for any packet, with room to spare.
+const RECEIVE_BUFFER_SIZE: usize = 2048;
+
+/// A message between copies of the game.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    Hello { version: u32 },
+    Welcome { version: u32, seed: u64, tick_rate: f32 },
+    /// The inputs for the ticks from `start` onwards, and the number of the receiver's ticks
+    /// that the sender has the input for.
+    Inputs { ack: u32, start: u32, inputs: Vec<PaddleInput> },
+    Join { version: u32, spectate: bool },
+    /// Tells a client which paddle it plays, or `None` if it is spectating.
+    Accept { version: u32, side: Option<Side>, tick_rate: f32 },
+    /// The state of the match after `tick` ticks, written by `Simulation::write_state`.
+    Snapshot { match_id: u64, tick: u32, ack: u32, state: Vec<u8> },
+    /// The changes to the match from the state after `base` ticks to the state after `tick`,
+    /// written by `state::write_delta`.
+    Delta { match_id: u64, tick: u32, base: u32, delta: Vec<u8> },
+}
+
+impl Packet {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        match *self {
+            Packet::Hello { version } => {
+                state::write_u8(&mut bytes, KIND_HELLO);
+                state::write_u32(&mut bytes, version);
+            }
+            Packet::Welcome { version, seed, tick_rate } => {
+                state::write_u8(&mut bytes, KIND_WELCOME);
+                state::write_u32(&mut bytes, version);
+                state::write_u64(&mut bytes, seed);
+                state::write_f32(&mut bytes, tick_rate);
+            }
+            Packet::Inputs { ack, start, ref inputs } => {
+                state::write_u8(&mut bytes, KIND_INPUTS);
+                state::write_u32(&mut bytes, ack);
+                state::write_u32(&mut bytes, start);
+                state::write_u8(&mut bytes, inputs.len().min(MAX_INPUTS_PER_PACKET) as u8);
+                for input in inputs.iter().take(MAX_INPUTS_PER_PACKET) {
+                    write_input(&mut bytes, input);
+                }
+            }
+            Packet::Join { version, spectate } => {
+                state::write_u8(&mut bytes, KIND_JOIN);
+                state::write_u32(&mut bytes, version);
+                state::write_u8(&mut bytes, spectate as u8);
+            }
+            Packet::Accept { version, side, tick_rate } => {
+                state::write_u8(&mut bytes, KIND_ACCEPT);
+                state::write_u32(&mut bytes, version);
+                state::write_u8(&mut bytes, match side {
+                    Some(Side::Left) => ROLE_LEFT,
+                    Some(Side::Right) => ROLE_RIGHT,
+                    None => ROLE_SPECTATOR,
+                });
+                state::write_f32(&mut bytes, tick_rate);
+            }
+            Packet::Snapshot { match_id, tick, ack, ref state } => {
+                state::write_u8(&mut bytes, KIND_SNAPSHOT);
+                state::write_u64(&mut bytes, match_id);
+                state::write_u32(&mut bytes, tick);
+                state::write_u32(&mut bytes, ack);
+                bytes.extend_from_slice(state);
+            }
+            Packet::Delta { match_id, tick, base, ref delta } => {
+                state::write_u8(&mut bytes, KIND_DELTA);
+                state::write_u64(&mut bytes, match_id);
+                state::write_u32(&mut bytes, tick);
+                state::write_u32(&mut bytes, base);
+                bytes.extend_from_slice(delta);
+            }
+        }
+        bytes
+    }
+
+    /// Reads a packet, or returns `None` if it isn't one.
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<Packet> {
+        let input = &mut bytes;
+        if !input.starts_with(MAGIC) {
+            return None;
+        }
+        *input = &input[MAGIC.len()..];
+        match state::read_u8(input)? {
+            KIND_HELLO => Some(Packet::Hello { version: state::read_u32(input)? }),
+            KIND_WELCOME => Some(Packet::Welcome {
+                version: state::read_u32(input)?,
+                seed: state::read_u64(input)?,
+                tick_rate: state::read_f32(input)?,
+            }),
+            KIND_INPUTS => {
+                let ack = state::read_u32(input)?;
+                let start = state::read_u32(input)?;
+                let count = state::read_u8(input)?;
+                let inputs = (0..count).map(|_| read_input(input)).collect::<Option<_>>()?;
+                Some(Packet::Inputs { ack, start, inputs })
+            }
+            KIND_JOIN => Some(Packet::Join {
+                version: state::read_u32(input)?,
+                spectate: state::read_u8(input)? != 0,
+            }),
+            KIND_ACCEPT => Some(Packet::Accept {
+                version: state::read_u32(input)?,
+                side: match state::read_u8(input)? {
+                    ROLE_LEFT => Some(Side::Left),
+                    ROLE_RIGHT => Some(Side::Right),
+                    ROLE_SPECTATOR => None,
+                    _ => return None,
+                },
+                tick_rate: state::read_f32(input)?,
+            }),
+            KIND_SNAPSHOT => Some(Packet::Snapshot {
+                match_id: state::read_u64(input)?,
+                tick: state::read_u32(input)?,
+                ack: state::read_u32(input)?,
+                state: input.to_vec(),
+            }),
+            KIND_DELTA => Some(Packet::Delta {
+                match_id: state::read_u64(input)?,
+                tick: state::read_u32(input)?,
+                base: state::read_u32(input)?,
+                delta: input.to_vec(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn write_input(bytes: &mut Vec<u8>, input: &PaddleInput) {
+    let mut flags = 0;
+    for &(set, flag) in &[
+        (input.up, FLAG_UP),
+        (input.down, FLAG_DOWN),
+        (input.serve, FLAG_SERVE),
+        (input.target_y.is_some(), FLAG_TARGET_Y),
+    ] {
+        if set {
+            flags |= flag;
+        }
+    }
+    state::write_u8(bytes, flags);
+    state::write_f32(bytes, input.axis);
+    if let Some(target_y) = input.target_y {
+        state::write_f32(bytes, target_y);
+    }
+}
+
+fn read_input(input: &mut &[u8]) -> Option<PaddleInput> {
+    let flags = state::read_u8(input)?;
+    let axis = state::read_f32(input)?;
+    let target_y = if flags & FLAG_TARGET_Y != 0 { Some(state::read_f32(input)?) } else { None };
+    if !(-1.0..=1.0).contains(&axis) || target_y.is_some_and(|target_y| !target_y.is_finite()) {
+        return None;
+    }
+    Some(PaddleInput {
+        up: flags & FLAG_UP != 0,
+        down: flags & FLAG_DOWN != 0,
+        axis,
+        target_y,
+        serve: flags & FLAG_SERVE != 0,
+    })
+}
+
+/// How much worse than it really is to make the network seem.
+/// Each packet is held back for `latency` plus a random amount up to `jitter`, which can put
+/// packets out of order, and a fraction `loss` of them are dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetConditions {
+    pub latency: Duration,
+    pub jitter: Duration,
+    pub loss: f32,
+}
+
+/// A UDP socket that never blocks, and which can make the network seem worse than it is.
+pub struct Link {
+    socket: UdpSocket,
+    conditions: NetConditions,
+    rng: GameRng,
+    // Packets being held back, with when to send them.
+    delayed: Vec<(Instant, SocketAddr, Vec<u8>)>,
+}
+
+impl Link {
+    /// Opens a socket on the given address, such as `0.0.0.0:7878`, or port 0 for any port.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Link> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Link {
+            socket,
+            conditions: NetConditions::default(),
+            rng: GameRng::from_entropy(),
+            delayed: Vec::new(),
+        })
+    }
+
+    /// Makes the packets sent from now on suffer from the given conditions.
+    pub fn set_conditions(&mut self, conditions: NetConditions) {
+        self.conditions = conditions;
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sends a packet, or holds it back if the link is pretending to be slow.
+    pub fn send_to(&mut self, packet: &Packet, addr: SocketAddr) -> io::Result<()> {
+        let bytes = packet.to_bytes();
+        let NetConditions { latency, jitter, loss } = self.conditions;
+        if loss > 0.0 && self.rng.gen::<f32>() < loss {
+            return Ok(());
+        }
+        if latency == Duration::ZERO && jitter == Duration::ZERO {
+            return self.send_now(&bytes, addr);
+        }
+        let delay = latency + jitter.mul_f32(self.rng.gen());
+        self.delayed.push((Instant::now() + delay, addr, bytes));
+        Ok(())
+    }
+
+    /// Returns the next packet that has arrived and who sent it, or `None` if there aren't any.
+    /// Also sends any held back packets that are due.
+    pub fn recv_from(&mut self) -> io::Result<Option<(Packet, SocketAddr)>> {
+        self.send_delayed()?;
+        let mut buffer = [0; RECEIVE_BUFFER_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, addr)) => {
+                    if let Some(packet) = Packet::from_bytes(&buffer[..len]) {
+                        return Ok(Some((packet, addr)));
+                    }
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                // Some systems report that an earlier packet couldn't be delivered,
+                // which is no reason to stop listening.
+                Err(ref err) if is_unreachable(err) => (),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn send_delayed(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let (due, waiting) = self.delayed.drain(..).partition(|&(time, _, _)| time <= now);
+        self.delayed = waiting;
+        for (_, addr, bytes) in due {
+            self.send_now(&bytes, addr)?;
+        }
+        Ok(())
+    }
+
+    fn send_now(&self, bytes: &[u8], addr: SocketAddr) -> io::Result<()> {
+        match self.socket.send_to(bytes, addr) {
+            Ok(_) => Ok(()),
+            // UDP makes no promises, so a packet that can't be sent right now is simply lost.
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock || is_unreachable(err) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn is_unreachable(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset)
+}
+
+/// An error in online play.
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    /// The other side speaks a different version of the protocol, which is given.
+    VersionMismatch(u32),
+    /// Nothing was heard from the other side for too long.
+    TimedOut,
+}
+
+impl From<io::Error> for NetError {
+    fn from(err: io::Error) -> NetError {
+        NetError::Io(err)
+    }
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetError::Io(ref err) => err.fmt(f),
+            NetError::VersionMismatch(version) => write!(
+                f,
+                "the other game speaks network protocol version {}, but this one speaks version {}",
+                version, PROTOCOL_VERSION
+            ),
+            NetError::TimedOut => f.write_str("no answer from the other game"),
+        }
+    }
+}
+
+impl StdError for NetError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            NetError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn packets_round_trip_through_bytes() {
+        let inputs = vec![
+            PaddleInput::default(),
+            PaddleInput { up: true, serve: true, ..PaddleInput::default() },
+            PaddleInput { axis: -0.5, target_y: Some(300.0), ..PaddleInput::default() },
+        ];
+        for packet in &[
+            Packet::Hello { version: PROTOCOL_VERSION },
+            Packet::Welcome { version: PROTOCOL_VERSION, seed: u64::MAX - 1, tick_rate: 60.0 },
+            Packet::Inputs { ack: 12, start: 40, inputs },
+            Packet::Join { version: PROTOCOL_VERSION, spectate: true },
+            Packet::Accept { version: PROTOCOL_VERSION, side: Some(Side::Right), tick_rate: 60.0 },
+            Packet::Snapshot { match_id: 9, tick: 5, ack: 3, state: vec![1, 2, 3] },
+            Packet::Delta { match_id: 9, tick: 6, base: 5, delta: vec![4, 5] },
+        ] {
+            let bytes = packet.to_bytes();
+            assert_eq!(Packet::from_bytes(&bytes).as_ref(), Some(packet));
+            // Snapshots and deltas run to the end of the packet, so only the match state can tell
+            // if they were cut short.
+            if !matches!(*packet, Packet::Snapshot { .. } | Packet::Delta { .. }) {
+                assert_eq!(Packet::from_bytes(&bytes[..bytes.len() - 1]), None);
+            }
+        }
+        assert_eq!(Packet::from_bytes(b"HTTP/1.1 200 OK"), None);
+
+        // Inputs that no controls could give are refused.
+        for input in &[
+            PaddleInput { axis: f32::NAN, ..PaddleInput::default() },
+            PaddleInput { axis: 1.5, ..PaddleInput::default() },
+            PaddleInput { target_y: Some(f32::NAN), ..PaddleInput::default() },
+            PaddleInput { target_y: Some(f32::INFINITY), ..PaddleInput::default() },
+        ] {
+            let packet = Packet::Inputs { ack: 0, start: 0, inputs: vec![*input] };
+            assert_eq!(Packet::from_bytes(&packet.to_bytes()), None);
+        }
+    }
+
+    fn receive(link: &mut Link, timeout: Duration) -> Option<Packet> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if let Some((packet, _)) = link.recv_from().unwrap() {
+                return Some(packet);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn shim_delays_and_drops_packets() {
+        let mut a = Link::bind("127.0.0.1:0").unwrap();
+        let mut b = Link::bind("127.0.0.1:0").unwrap();
+        let b_addr = b.local_addr().unwrap();
+        let hello = Packet::Hello { version: PROTOCOL_VERSION };
+
+        a.send_to(&hello, b_addr).unwrap();
+        assert_eq!(receive(&mut b, Duration::from_secs(5)), Some(hello.clone()));
+
+        a.set_conditions(NetConditions { latency: Duration::from_millis(100), ..NetConditions::default() });
+        let sent = Instant::now();
+        a.send_to(&hello, b_addr).unwrap();
+        assert_eq!(receive(&mut b, Duration::from_millis(50)), None);
+        // The held back packet goes out the next time the link is used.
+        thread::sleep(Duration::from_millis(60));
+        a.recv_from().unwrap();
+        assert_eq!(receive(&mut b, Duration::from_secs(5)), Some(hello.clone()));
+        assert!(sent.elapsed() >= Duration::from_millis(100));
+
+        a.set_conditions(NetConditions { loss: 1.0, ..NetConditions::default() });
+        a.send_to(&hello, b_addr).unwrap();
+        assert_eq!(receive(&mut b, Duration::from_millis(50)), None);
+    }
+}
@@ -1,49 +1,155 @@
 //! Represents a player's paddle in a game of Pong.
 
-use {Game, Rectangle, Result, UpdateParams};
-use glium::Frame;
-use glium::glutin::VirtualKeyCode;
-use std::collections::HashSet;
+use state;
+use {Bindings, Rectangle, UpdateParams};
 
-const KEYBOARD_SPEED: f32 = 500.0;
+pub const KEYBOARD_SPEED: f32 = 500.0;
+const POINTER_SPEED: f32 = 900.0;
 const WIDTH: f32 = 20.0;
 const HEIGHT: f32 = 100.0;
 
-/// Each paddle has a position and a score.
+/// The side of the court that a player defends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    /// Returns the other side of the court.
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+
+    /// The lowercase name of the side, as used in files and messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        }
+    }
+}
+
+/// The controls for a paddle during one tick.
+/// Holding up or down moves the paddle at full speed. Otherwise, `axis` moves it at a fraction
+/// of full speed, from -1 for up to 1 for down, such as from an analog stick. Failing that,
+/// the paddle heads towards `target_y` when set, such as the mouse position, unless the other
+/// controls have moved the paddle since it last changed.
+/// `serve` asks for the ball to be served without waiting for the countdown.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PaddleInput {
+    pub up: bool,
+    pub down: bool,
+    pub axis: f32,
+    pub target_y: Option<f32>,
+    pub serve: bool,
+}
+
+impl PaddleInput {
+    /// Combines the controls from two sources, such as the keyboard and a gamepad,
+    /// as if one player were using both.
+    pub fn merge(&self, other: &PaddleInput) -> PaddleInput {
+        PaddleInput {
+            up: self.up || other.up,
+            down: self.down || other.down,
+            axis: if self.axis != 0.0 { self.axis } else { other.axis },
+            target_y: self.target_y.or(other.target_y),
+            serve: self.serve || other.serve,
+        }
+    }
+}
+
+/// Each paddle has a position, a score, the velocity it moved at during the last frame,
+/// and the inputs that control it.
+/// `pointer_speed` limits how fast the paddle can chase the mouse or a touch.
 #[derive(Clone, Debug)]
 pub struct Paddle {
     pub bounds: Rectangle,
+    pub previous_bounds: Rectangle,
     pub score: u32,
+    pub vy: f32,
+    pub binding: Bindings,
+    pub pointer_speed: f32,
+
+    // Where the pointer was the last time other controls moved the paddle. The paddle doesn't go
+    // back there when they're let go, only once the pointer moves again.
+    stale_target_y: Option<f32>,
 }
 
 impl Paddle {
-    /// Creates a paddle at the given position.
+    /// Creates a paddle at the given position, controlled by the arrow keys.
     /// The paddle can only move vertically.
     pub fn new(x: f32, y: f32) -> Paddle {
+        Paddle::with_binding(x, y, Bindings::arrows())
+    }
+
+    /// Creates a paddle at the given position, controlled by the given bindings.
+    pub fn with_binding(x: f32, y: f32, binding: Bindings) -> Paddle {
+        let bounds = Rectangle::new_centered(x, y, WIDTH, HEIGHT);
         Paddle {
-            bounds: Rectangle {
-                x: x - WIDTH / 2.0,
-                y: y - HEIGHT / 2.0,
-                width: WIDTH,
-                height: HEIGHT,
-            },
+            bounds,
+            previous_bounds: bounds,
             score: 0,
+            vy: 0.0,
+            binding,
+            pointer_speed: POINTER_SPEED,
+            stale_target_y: None,
         }
     }
 
+    /// Writes everything about the paddle that affects how the match plays out, bit for bit.
+    /// The bindings only affect how input is read, so they are left out.
+    pub fn write_state(&self, out: &mut Vec<u8>) {
+        self.bounds.write_state(out);
+        self.previous_bounds.write_state(out);
+        state::write_u32(out, self.score);
+        state::write_f32(out, self.vy);
+        state::write_f32(out, self.pointer_speed);
+        state::write_u8(out, self.stale_target_y.is_some() as u8);
+        state::write_f32(out, self.stale_target_y.unwrap_or(0.0));
+    }
+
+    /// Restores the state written by `write_state`.
+    pub fn read_state(&mut self, input: &mut &[u8]) -> Option<()> {
+        self.bounds = Rectangle::read_state(input)?;
+        self.previous_bounds = Rectangle::read_state(input)?;
+        self.score = state::read_u32(input)?;
+        self.vy = state::read_f32(input)?;
+        self.pointer_speed = state::read_f32(input)?;
+        let has_stale_target = state::read_u8(input)? != 0;
+        let stale_target_y = state::read_f32(input)?;
+        self.stale_target_y = if has_stale_target { Some(stale_target_y) } else { None };
+        Some(())
+    }
+
     /// Updates the state of the paddle based on player input.
-    pub fn update(&mut self, params: &UpdateParams, pressed_keys: &HashSet<VirtualKeyCode>) {
-        /// Move the paddle if a particular key is pressed.
-        // TODO: Allow the left and right paddles to be controlled via different keys/mouse.
-        let mut vy = 0.0;
-        if pressed_keys.contains(&VirtualKeyCode::Up) {
-            vy -= KEYBOARD_SPEED;
-        }
-        if pressed_keys.contains(&VirtualKeyCode::Down) {
-            vy += KEYBOARD_SPEED;
-        }
+    pub fn update(&mut self, params: &UpdateParams, input: &PaddleInput) {
+        self.previous_bounds = self.bounds;
 
-        self.bounds.y += vy * params.dt; 
+        if input.up || input.down {
+            // Move the paddle if a particular control is held.
+            let mut vy = 0.0;
+            if input.up {
+                vy -= KEYBOARD_SPEED;
+            }
+            if input.down {
+                vy += KEYBOARD_SPEED;
+            }
+            self.bounds.y += vy * params.dt;
+            self.stale_target_y = input.target_y;
+        } else if input.axis != 0.0 {
+            // Move in proportion to how far the stick is pushed.
+            self.bounds.y += input.axis.clamp(-1.0, 1.0) * KEYBOARD_SPEED * params.dt;
+            self.stale_target_y = input.target_y;
+        } else if let Some(target_y) = input.target_y.filter(|&target_y| Some(target_y) != self.stale_target_y) {
+            // Head towards the pointer, without moving faster than the speed limit.
+            let distance = target_y - (self.bounds.y + self.bounds.height / 2.0);
+            let max_distance = self.pointer_speed * params.dt;
+            self.bounds.y += distance.clamp(-max_distance, max_distance);
+        }
 
         // Clamp the paddle position on screen.
         if self.bounds.y < 0.0 {
@@ -51,10 +157,96 @@ impl Paddle {
         } else if self.bounds.y + self.bounds.height > params.game_height {
             self.bounds.y = params.game_height - self.bounds.height;
         }
+
+        // Remember how fast the paddle actually moved so that it can put spin on the ball.
+        self.vy = if params.dt > 0.0 { (self.bounds.y - self.previous_bounds.y) / params.dt } else { 0.0 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> UpdateParams {
+        UpdateParams {
+            dt: 0.1,
+            game_width: 1280.0,
+            game_height: 720.0,
+        }
+    }
+
+    #[test]
+    fn tracks_velocity() {
+        let mut paddle = Paddle::new(25.0, 360.0);
+        paddle.update(&params(), &PaddleInput { down: true, ..PaddleInput::default() });
+        assert_eq!(paddle.vy, KEYBOARD_SPEED);
+
+        paddle.update(&params(), &PaddleInput::default());
+        assert_eq!(paddle.vy, 0.0);
+    }
+
+    #[test]
+    fn velocity_is_zero_against_the_wall() {
+        let mut paddle = Paddle::new(25.0, HEIGHT / 2.0);
+        paddle.update(&params(), &PaddleInput { up: true, ..PaddleInput::default() });
+        assert_eq!(paddle.bounds.y, 0.0);
+        assert_eq!(paddle.vy, 0.0);
     }
 
-    /// Draws the paddle on the screen.
-    pub fn render(&self, game: &Game, frame: &mut Frame) -> Result<()> {
-        game.draw_rectangle(frame, self.bounds, [1.0, 1.0, 1.0, 1.0])
+    #[test]
+    fn follows_pointer_at_limited_speed() {
+        let mut paddle = Paddle::new(25.0, 360.0);
+        let input = PaddleInput { target_y: Some(100.0), ..PaddleInput::default() };
+        paddle.update(&params(), &input);
+        assert_eq!(paddle.vy, -POINTER_SPEED);
+
+        // Close enough to reach the pointer in one update.
+        let input = PaddleInput { target_y: Some(280.0), ..PaddleInput::default() };
+        paddle.update(&params(), &input);
+        assert_eq!(paddle.bounds.y + paddle.bounds.height / 2.0, 280.0);
+    }
+
+    #[test]
+    fn axis_moves_at_a_fraction_of_full_speed() {
+        let mut paddle = Paddle::new(25.0, 360.0);
+        let input = PaddleInput { axis: -0.5, target_y: Some(720.0), ..PaddleInput::default() };
+        paddle.update(&params(), &input);
+        assert_eq!(paddle.vy, -KEYBOARD_SPEED / 2.0);
+
+        let input = PaddleInput { up: true, axis: 1.0, ..PaddleInput::default() };
+        paddle.update(&params(), &input);
+        assert_eq!(paddle.vy, -KEYBOARD_SPEED);
+    }
+
+    #[test]
+    fn merges_input_sources() {
+        let keyboard = PaddleInput { up: true, target_y: Some(100.0), ..PaddleInput::default() };
+        let gamepad = PaddleInput { axis: 0.5, serve: true, target_y: Some(200.0), ..PaddleInput::default() };
+        let merged = keyboard.merge(&gamepad);
+        assert_eq!(merged, PaddleInput { up: true, down: false, axis: 0.5, target_y: Some(100.0), serve: true });
+    }
+
+    #[test]
+    fn keys_override_pointer() {
+        let mut paddle = Paddle::new(25.0, 360.0);
+        let input = PaddleInput { down: true, target_y: Some(0.0), ..PaddleInput::default() };
+        paddle.update(&params(), &input);
+        assert_eq!(paddle.vy, KEYBOARD_SPEED);
+    }
+
+    #[test]
+    fn holds_still_after_keys_until_pointer_moves() {
+        let mut paddle = Paddle::new(25.0, 360.0);
+        let input = PaddleInput { down: true, target_y: Some(100.0), ..PaddleInput::default() };
+        paddle.update(&params(), &input);
+
+        // Letting go of the key leaves the paddle where it is, not heading back to the pointer.
+        let input = PaddleInput { target_y: Some(100.0), ..PaddleInput::default() };
+        paddle.update(&params(), &input);
+        assert_eq!(paddle.vy, 0.0);
+
+        let input = PaddleInput { target_y: Some(90.0), ..PaddleInput::default() };
+        paddle.update(&params(), &input);
+        assert_eq!(paddle.vy, -POINTER_SPEED);
     }
 }
@@ -1,6 +1,9 @@
 //! Represents a quad or axis-aligned bounding box.
 
-#[derive(Clone, Copy, Debug)]
+use state;
+use std::f32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Rectangle {
     pub x: f32,
     pub y: f32,
@@ -11,7 +14,7 @@ pub struct Rectangle {
 impl Rectangle {
     /// Creates a new rectangle with the top-left corner at the specified position.
     pub fn new(x: f32, y: f32, width: f32, height: f32) -> Rectangle {
-        Rectangle { x: x, y: y, width: width, height: height }
+        Rectangle { x, y, width, height }
     }
 
     /// Creates a new rectangle centered at the specified position.
@@ -19,11 +22,28 @@ impl Rectangle {
         Rectangle {
             x: x - width / 2.0,
             y: y - height / 2.0,
-            width: width,
-            height: height
+            width,
+            height,
+        }
+    }
+
+    /// Writes the exact bits of the rectangle, for snapshots and state hashes.
+    pub fn write_state(&self, out: &mut Vec<u8>) {
+        for &value in &[self.x, self.y, self.width, self.height] {
+            state::write_f32(out, value);
         }
     }
 
+    /// Reads a rectangle written by `write_state`.
+    pub fn read_state(input: &mut &[u8]) -> Option<Rectangle> {
+        Some(Rectangle {
+            x: state::read_f32(input)?,
+            y: state::read_f32(input)?,
+            width: state::read_f32(input)?,
+            height: state::read_f32(input)?,
+        })
+    }
+
     /// Tests if `self` contains the given point.
     #[allow(dead_code)]
     pub fn contains_point(&self, x: f32, y: f32) -> bool {
@@ -35,4 +55,112 @@ impl Rectangle {
         self.x <= other.x + other.width && self.x + self.width >= other.x &&
         self.y <= other.y + other.height && self.y + self.height >= other.y
     }
+
+    /// Linearly interpolates the position between `self` and `other`.
+    /// `t` ranges from 0 (returns `self`) to 1 (returns `other`'s position).
+    pub fn lerp(&self, other: Rectangle, t: f32) -> Rectangle {
+        Rectangle {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+            width: other.width,
+            height: other.height,
+        }
+    }
+
+    /// Sweeps `self` along the displacement `(dx, dy)` and finds when it first touches `other`.
+    /// Returns `None` if the rectangles don't meet during the move, or if they already overlap
+    /// at the start. Rectangles that only slide along each other's edges are not a hit.
+    pub fn sweep(&self, dx: f32, dy: f32, other: Rectangle) -> Option<SweepHit> {
+        let (x_entry, x_exit) = sweep_axis(self.x, self.width, dx, other.x, other.width)?;
+        let (y_entry, y_exit) = sweep_axis(self.y, self.height, dy, other.y, other.height)?;
+
+        let entry = f32::max(x_entry, y_entry);
+        let exit = f32::min(x_exit, y_exit);
+        if entry >= exit || !(0.0..=1.0).contains(&entry) {
+            return None;
+        }
+
+        // The axis that was reached last is the face that was hit.
+        let (normal_x, normal_y) = if x_entry > y_entry {
+            (-dx.signum(), 0.0)
+        } else {
+            (0.0, -dy.signum())
+        };
+        Some(SweepHit { time: entry, normal_x, normal_y })
+    }
+}
+
+/// The result of a successful `Rectangle::sweep`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+    /// The fraction of the displacement travelled before touching, from 0 to 1.
+    pub time: f32,
+    /// The normal of the face that was hit, pointing back towards the moving rectangle.
+    pub normal_x: f32,
+    pub normal_y: f32,
+}
+
+/// Finds the fractions of a one-dimensional move at which a moving span starts and stops
+/// overlapping a stationary span. Returns `None` if they can never overlap on this axis.
+fn sweep_axis(pos: f32, size: f32, delta: f32, other_pos: f32, other_size: f32) -> Option<(f32, f32)> {
+    if delta > 0.0 {
+        Some(((other_pos - (pos + size)) / delta, (other_pos + other_size - pos) / delta))
+    } else if delta < 0.0 {
+        Some(((other_pos + other_size - pos) / delta, (other_pos - (pos + size)) / delta))
+    } else if pos < other_pos + other_size && pos + size > other_pos {
+        Some((f32::NEG_INFINITY, f32::INFINITY))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_hits_face_in_path() {
+        let ball = Rectangle::new(0.0, 0.0, 10.0, 10.0);
+        let wall = Rectangle::new(50.0, -20.0, 20.0, 50.0);
+        let hit = ball.sweep(80.0, 0.0, wall).unwrap();
+        assert_eq!(hit.time, 0.5);
+        assert_eq!((hit.normal_x, hit.normal_y), (-1.0, 0.0));
+    }
+
+    #[test]
+    fn sweep_passes_through_thin_rectangle() {
+        // A discrete check at either end of the move would miss the wall entirely.
+        let ball = Rectangle::new(100.0, 0.0, 10.0, 10.0);
+        let wall = Rectangle::new(40.0, -20.0, 20.0, 50.0);
+        assert!(!ball.intersects(wall));
+        let hit = ball.sweep(-100.0, 0.0, wall).unwrap();
+        assert_eq!(hit.time, 0.4);
+        assert_eq!((hit.normal_x, hit.normal_y), (1.0, 0.0));
+    }
+
+    #[test]
+    fn sweep_diagonal_hits_top_face() {
+        let ball = Rectangle::new(0.0, 0.0, 10.0, 10.0);
+        let floor = Rectangle::new(-100.0, 30.0, 200.0, 10.0);
+        let hit = ball.sweep(20.0, 40.0, floor).unwrap();
+        assert_eq!(hit.time, 0.5);
+        assert_eq!((hit.normal_x, hit.normal_y), (0.0, -1.0));
+    }
+
+    #[test]
+    fn sweep_misses() {
+        let ball = Rectangle::new(0.0, 0.0, 10.0, 10.0);
+        let wall = Rectangle::new(50.0, 20.0, 20.0, 50.0);
+        assert_eq!(ball.sweep(80.0, 0.0, wall), None);
+        assert_eq!(ball.sweep(20.0, 0.0, wall), None);
+        assert_eq!(ball.sweep(-80.0, 0.0, wall), None);
+    }
+
+    #[test]
+    fn sweep_ignores_sliding_and_moving_away() {
+        let ball = Rectangle::new(0.0, 0.0, 10.0, 10.0);
+        let floor = Rectangle::new(-100.0, 10.0, 200.0, 10.0);
+        assert_eq!(ball.sweep(50.0, 0.0, floor), None);
+        assert_eq!(ball.sweep(50.0, -10.0, floor), None);
+    }
 }
@@ -0,0 +1,358 @@
+//! Draws the game through an abstract `Renderer`, so that the same drawing code can target
+//! OpenGL, nothing at all, or a list of draw calls for tests.
+
+use {Ball, Paddle, Rectangle, Side, Simulation, SCORE_TO_WIN};
+use std::convert::Infallible;
+
+/// An RGBA color with each component between 0 and 1.
+pub type Color = [f32; 4];
+
+const BACKGROUND_COLOR: Color = [0.0, 0.0, 0.0, 1.0];
+const NET_COLOR: Color = [0.1, 0.1, 0.1, 1.0];
+const SCORE_COLOR: Color = [0.2, 0.2, 0.2, 1.0];
+const WINNING_SCORE_COLOR: Color = [1.0, 0.2, 0.2, 1.0];
+const COUNTDOWN_COLOR: Color = [0.6, 0.6, 0.6, 1.0];
+const OBJECT_COLOR: Color = [1.0, 1.0, 1.0, 1.0];
+const PREDICTION_COLOR: Color = [0.2, 0.6, 1.0, 1.0];
+const REPLAY_COLOR: Color = [0.8, 0.8, 0.2, 1.0];
+
+// Each digit is five rows of three pixels, with the leftmost pixel in the highest bit.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Maps between game coordinates and the pixels of a frame that the game is drawn into.
+/// The game is scaled to fit inside the frame while keeping its aspect ratio, and centered,
+/// leaving black bars on two sides if the aspect ratios differ.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Letterbox {
+    pub scale: f32,
+    pub offset_x: f32,
+    pub offset_y: f32,
+}
+
+impl Letterbox {
+    pub fn new(frame_width: f32, frame_height: f32, game_width: f32, game_height: f32) -> Letterbox {
+        let scale = f32::min(frame_width / game_width, frame_height / game_height);
+        Letterbox {
+            scale,
+            offset_x: (frame_width - game_width * scale) / 2.0,
+            offset_y: (frame_height - game_height * scale) / 2.0,
+        }
+    }
+
+    /// Converts a point in game coordinates to frame pixel coordinates.
+    pub fn to_frame(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.offset_x + x * self.scale, self.offset_y + y * self.scale)
+    }
+
+    /// Converts a point in frame pixel coordinates, such as the mouse cursor, to game coordinates.
+    pub fn to_game(&self, x: f32, y: f32) -> (f32, f32) {
+        ((x - self.offset_x) / self.scale, (y - self.offset_y) / self.scale)
+    }
+}
+
+/// A target that the game can be drawn onto.
+/// All coordinates are in game space (1280x720) with (0, 0) at the top left.
+// TODO: Add text drawing.
+pub trait Renderer {
+    type Error;
+
+    /// Fills the whole target with a color.
+    fn clear(&mut self, color: Color) -> Result<(), Self::Error>;
+
+    /// Draws a solidly filled rectangle.
+    fn draw_rectangle(&mut self, rect: Rectangle, color: Color) -> Result<(), Self::Error>;
+}
+
+/// A renderer that ignores everything drawn to it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullRenderer;
+
+impl Renderer for NullRenderer {
+    type Error = Infallible;
+
+    fn clear(&mut self, _color: Color) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn draw_rectangle(&mut self, _rect: Rectangle, _color: Color) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// A single call made to a `Renderer`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrawCommand {
+    Clear(Color),
+    Rectangle(Rectangle, Color),
+}
+
+/// A renderer that remembers every call made to it, in order.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingRenderer {
+    pub commands: Vec<DrawCommand>,
+}
+
+impl RecordingRenderer {
+    pub fn new() -> RecordingRenderer {
+        RecordingRenderer { commands: Vec::new() }
+    }
+
+    /// Returns the rectangles that were drawn in the given color.
+    pub fn rectangles_with_color(&self, color: Color) -> Vec<Rectangle> {
+        self.commands
+            .iter()
+            .filter_map(|command| match *command {
+                DrawCommand::Rectangle(rect, c) if c == color => Some(rect),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Renderer for RecordingRenderer {
+    type Error = Infallible;
+
+    fn clear(&mut self, color: Color) -> Result<(), Infallible> {
+        self.commands.push(DrawCommand::Clear(color));
+        Ok(())
+    }
+
+    fn draw_rectangle(&mut self, rect: Rectangle, color: Color) -> Result<(), Infallible> {
+        self.commands.push(DrawCommand::Rectangle(rect, color));
+        Ok(())
+    }
+}
+
+/// Draws a complete frame of the game.
+/// `alpha` is how far the frame is between the previous tick and the current one.
+pub fn draw_game<R: Renderer>(renderer: &mut R, simulation: &Simulation, alpha: f32) -> Result<(), R::Error> {
+    // Clear the screen.
+    renderer.clear(BACKGROUND_COLOR)?;
+
+    // Draw the various UI elements.
+    let width = simulation.width();
+    draw_net(renderer, width, simulation.height())?;
+    draw_score(renderer, simulation.left_paddle().score, width * 0.25, 10.0)?;
+    draw_score(renderer, simulation.right_paddle().score, width * 0.75, 10.0)?;
+
+    // Draw the player paddles.
+    draw_paddle(renderer, simulation.left_paddle(), alpha)?;
+    draw_paddle(renderer, simulation.right_paddle(), alpha)?;
+
+    // Draw the ball.
+    draw_ball(renderer, simulation.ball(), alpha)
+}
+
+/// Draws a dotted line in the middle of the screen.
+pub fn draw_net<R: Renderer>(renderer: &mut R, width: f32, height: f32) -> Result<(), R::Error> {
+    const NET_WIDTH: f32 = 8.0;
+    const NET_SEGMENT_HEIGHT: f32 = 50.0;
+    let mut rect = Rectangle {
+        x: (width - NET_WIDTH) / 2.0,
+        y: 0.0,
+        width: NET_WIDTH,
+        height: NET_SEGMENT_HEIGHT
+    };
+
+    while rect.y < height {
+        renderer.draw_rectangle(rect, NET_COLOR)?;
+        rect.y += NET_SEGMENT_HEIGHT * 1.5;
+    }
+    Ok(())
+}
+
+/// Draws a series of dots representing the score for a player.
+pub fn draw_score<R: Renderer>(renderer: &mut R, score: u32, x: f32, y: f32) -> Result<(), R::Error> {
+    const ROW_LENGTH: u32 = 5;
+    let mut rect = Rectangle {
+        x: 0.0,
+        y: 0.0,
+        width: 5.0,
+        height: 5.0
+    };
+    // Draw winning score in red.
+    let color = if score < SCORE_TO_WIN { SCORE_COLOR } else { WINNING_SCORE_COLOR };
+    for i in 0..score {
+        let column = (i % ROW_LENGTH) as f32;
+        let row = (i / ROW_LENGTH) as f32;
+        rect.x = x + 8.0 * column;
+        rect.y = y + 8.0 * row;
+        renderer.draw_rectangle(rect, color)?;
+    }
+    Ok(())
+}
+
+/// Draws a paddle, `alpha` of the way from the previous tick to the current one.
+pub fn draw_paddle<R: Renderer>(renderer: &mut R, paddle: &Paddle, alpha: f32) -> Result<(), R::Error> {
+    renderer.draw_rectangle(paddle.previous_bounds.lerp(paddle.bounds, alpha), OBJECT_COLOR)
+}
+
+/// Draws the ball, `alpha` of the way from the previous tick to the current one.
+/// While waiting to be served, a dot is drawn above the ball for each second remaining.
+pub fn draw_ball<R: Renderer>(renderer: &mut R, ball: &Ball, alpha: f32) -> Result<(), R::Error> {
+    let bounds = ball.previous_bounds().lerp(ball.bounds(), alpha);
+    if ball.start_timer() > 0.0 {
+        const DOT_SIZE: f32 = 5.0;
+        const DOT_SPACING: f32 = 8.0;
+        let dots = ball.start_timer().ceil() as u32;
+        let row_width = DOT_SPACING * dots as f32 - (DOT_SPACING - DOT_SIZE);
+        let mut rect = Rectangle {
+            x: bounds.x + (bounds.width - row_width) / 2.0,
+            y: bounds.y - 2.0 * DOT_SPACING,
+            width: DOT_SIZE,
+            height: DOT_SIZE,
+        };
+        for _ in 0..dots {
+            renderer.draw_rectangle(rect, COUNTDOWN_COLOR)?;
+            rect.x += DOT_SPACING;
+        }
+    }
+    renderer.draw_rectangle(bounds, OBJECT_COLOR)
+}
+
+/// Draws a number in blocky digits with its top left corner at `x` and `y`, made of square
+/// pixels `size` wide.
+pub fn draw_number<R: Renderer>(renderer: &mut R, number: u64, x: f32, y: f32, size: f32, color: Color) -> Result<(), R::Error> {
+    let text = number.to_string();
+    for (i, digit) in text.bytes().enumerate() {
+        let left = x + i as f32 * 4.0 * size;
+        for (row, &bits) in DIGITS[usize::from(digit - b'0')].iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    let pixel = Rectangle::new(left + column as f32 * size, y + row as f32 * size, size, size);
+                    renderer.draw_rectangle(pixel, color)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Draws how far through a replay the match is: the number of ticks played in the bottom
+/// left corner, over a bar along the bottom of the screen.
+pub fn draw_replay_progress<R: Renderer>(renderer: &mut R, simulation: &Simulation, tick: usize, tick_count: usize) -> Result<(), R::Error> {
+    const BAR_HEIGHT: f32 = 4.0;
+    const DIGIT_SIZE: f32 = 4.0;
+    let (width, height) = (simulation.width(), simulation.height());
+    let progress = if tick_count > 0 { tick as f32 / tick_count as f32 } else { 1.0 };
+    renderer.draw_rectangle(Rectangle::new(0.0, height - BAR_HEIGHT, width * progress, BAR_HEIGHT), REPLAY_COLOR)?;
+    let y = height - BAR_HEIGHT - 8.0 * DIGIT_SIZE;
+    draw_number(renderer, tick as u64, 10.0, y, DIGIT_SIZE, REPLAY_COLOR)
+}
+
+/// Draws a debugging marker where the ball is predicted to reach the paddle it's heading for,
+/// using updates of `dt` seconds for the prediction.
+pub fn draw_prediction<R: Renderer>(renderer: &mut R, simulation: &Simulation, dt: f32) -> Result<(), R::Error> {
+    const MARKER_HEIGHT: f32 = 4.0;
+    for &side in &[Side::Left, Side::Right] {
+        if let Some(y) = simulation.predict_intercept(side, dt) {
+            let paddle = simulation.paddle(side).bounds;
+            let marker = Rectangle::new(paddle.x, y - MARKER_HEIGHT / 2.0, paddle.width, MARKER_HEIGHT);
+            renderer.draw_rectangle(marker, PREDICTION_COLOR)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {ServeSettings, GAME_HEIGHT, GAME_WIDTH};
+
+    #[test]
+    fn letterbox_round_trips() {
+        // A 16:9 game in a 4:3 window has bars above and below.
+        let letterbox = Letterbox::new(800.0, 600.0, 1280.0, 720.0);
+        assert_eq!(letterbox.scale, 0.625);
+        assert_eq!((letterbox.offset_x, letterbox.offset_y), (0.0, 75.0));
+        assert_eq!(letterbox.to_frame(1280.0, 0.0), (800.0, 75.0));
+        assert_eq!(letterbox.to_game(400.0, 300.0), (640.0, 360.0));
+        assert_eq!(letterbox.to_game(0.0, 0.0), (0.0, -120.0));
+    }
+
+    #[test]
+    fn net_covers_the_height_of_the_screen() {
+        let mut renderer = RecordingRenderer::new();
+        draw_net(&mut renderer, 1280.0, 720.0).unwrap();
+        let segments = renderer.rectangles_with_color(NET_COLOR);
+        assert_eq!(segments.len(), 10);
+        assert!(segments.iter().all(|rect| rect.x + rect.width / 2.0 == 640.0));
+        assert_eq!(segments[9].y, 9.0 * 75.0);
+    }
+
+    #[test]
+    fn score_is_drawn_in_rows_of_five() {
+        let mut renderer = RecordingRenderer::new();
+        draw_score(&mut renderer, 7, 100.0, 10.0).unwrap();
+        let dots = renderer.rectangles_with_color(SCORE_COLOR);
+        assert_eq!(dots.len(), 7);
+        assert_eq!((dots[4].x, dots[4].y), (132.0, 10.0));
+        assert_eq!((dots[5].x, dots[5].y), (100.0, 18.0));
+    }
+
+    #[test]
+    fn winning_score_is_red() {
+        let mut renderer = RecordingRenderer::new();
+        draw_score(&mut renderer, SCORE_TO_WIN, 100.0, 10.0).unwrap();
+        assert_eq!(renderer.rectangles_with_color(WINNING_SCORE_COLOR).len(), SCORE_TO_WIN as usize);
+        assert!(renderer.rectangles_with_color(SCORE_COLOR).is_empty());
+    }
+
+    #[test]
+    fn numbers_are_drawn_in_pixels() {
+        let mut renderer = RecordingRenderer::new();
+        draw_number(&mut renderer, 8, 0.0, 0.0, 2.0, REPLAY_COLOR).unwrap();
+        assert_eq!(renderer.rectangles_with_color(REPLAY_COLOR).len(), 13);
+
+        // Each digit is drawn to the right of the last, leaving a pixel between them.
+        let mut renderer = RecordingRenderer::new();
+        draw_number(&mut renderer, 10, 0.0, 0.0, 2.0, REPLAY_COLOR).unwrap();
+        let pixels = renderer.rectangles_with_color(REPLAY_COLOR);
+        assert_eq!(pixels.len(), 8 + 12);
+        assert_eq!(pixels.iter().map(|pixel| pixel.x).fold(0.0, f32::max), 4.0 * 2.0 + 2.0 * 2.0);
+    }
+
+    #[test]
+    fn prediction_marks_receiving_paddle() {
+        let simulation = Simulation::new(GAME_WIDTH as f32, GAME_HEIGHT as f32);
+        let mut renderer = RecordingRenderer::new();
+        draw_prediction(&mut renderer, &simulation, 1.0 / 60.0).unwrap();
+        let markers = renderer.rectangles_with_color(PREDICTION_COLOR);
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].x, simulation.paddle(simulation.ball().receiver()).bounds.x);
+    }
+
+    #[test]
+    fn draws_full_frame() {
+        let (width, height) = (GAME_WIDTH as f32, GAME_HEIGHT as f32);
+        let ball = Ball::with_serve_settings(width / 2.0, height / 2.0, ServeSettings {
+            delay: 2.5,
+            ..ServeSettings::default()
+        });
+        let simulation = Simulation::with_ball(width, height, ball);
+        let mut renderer = RecordingRenderer::new();
+        draw_game(&mut renderer, &simulation, 1.0).unwrap();
+
+        assert_eq!(renderer.commands[0], DrawCommand::Clear(BACKGROUND_COLOR));
+        assert_eq!(renderer.rectangles_with_color(COUNTDOWN_COLOR).len(), 3);
+
+        // Two paddles and the ball, with the ball drawn last.
+        let objects = renderer.rectangles_with_color(OBJECT_COLOR);
+        assert_eq!(objects.len(), 3);
+        assert_eq!(objects[0], simulation.left_paddle().bounds);
+        assert_eq!(objects[1], simulation.right_paddle().bounds);
+        assert_eq!(objects[2], simulation.ball().bounds());
+        assert_eq!(*renderer.commands.last().unwrap(), DrawCommand::Rectangle(objects[2], OBJECT_COLOR));
+    }
+}
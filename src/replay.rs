@@ -0,0 +1,587 @@
+//! Recordings of matches that can be played back exactly, for reproducing bugs.
+//!
+//! A match is decided entirely by its seed, its settings and the input for each tick, so a
+//! replay stores only those, plus snapshots of the match every `SNAPSHOT_INTERVAL` ticks so
+//! that a `ReplayViewer` can jump to any tick quickly. Playing the inputs back through a
+//! simulation created by `Replay::simulation` gives the same match, bit for bit.
+//!
+//! # File format, version 2
+//!
+//! All numbers are little-endian. Counts are unsigned LEB128 varints: seven bits per byte,
+//! lowest first, with the top bit set on every byte but the last.
+//!
+//! | Field       | Encoding                                                          |
+//! |-------------|-------------------------------------------------------------------|
+//! | magic       | the bytes `RPRP`                                                  |
+//! | version     | u16                                                               |
+//! | seed        | u64                                                               |
+//! | width       | f32, the width of the court                                       |
+//! | height      | f32, the height of the court                                      |
+//! | tick rate   | f32, ticks per second                                             |
+//! | final hash  | u8 1 then the u64 `Simulation::state_hash` after the last tick, or u8 0 |
+//! | tick count  | varint, at most `MAX_TICKS`                                       |
+//! | runs        | until all the ticks are covered: a varint number of ticks, then the input for the left and right paddles that is repeated for those ticks |
+//! | snapshots   | a varint count, then for each, in order: the varint tick it was taken after, a varint length, and that many bytes from `Simulation::write_state` |
+//!
+//! The input for a paddle is a byte of flags, followed by the values that the flags say are
+//! there: 1 for up, 2 for down, 4 for serve, 8 when an f32 axis follows, and 16 when an f32
+//! target y follows.
+//!
+//! Version 1 files are the same without snapshots. They can still be played, but their final
+//! hash came from an older way of hashing the match, so it isn't checked.
+
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use {Input, PaddleInput, Simulation};
+
+/// The version of the file format that is written.
+pub const REPLAY_VERSION: u16 = 2;
+
+/// How many ticks apart snapshots are taken while recording.
+pub const SNAPSHOT_INTERVAL: usize = 300;
+
+/// The most ticks that a replay file can hold, which is over a day at 60 ticks a second.
+/// This stops a damaged file from asking for more memory than there is.
+pub const MAX_TICKS: u64 = 1 << 23;
+
+const MAGIC: &[u8] = b"RPRP";
+
+const FLAG_UP: u8 = 1;
+const FLAG_DOWN: u8 = 2;
+const FLAG_SERVE: u8 = 4;
+const FLAG_AXIS: u8 = 8;
+const FLAG_TARGET_Y: u8 = 16;
+
+/// A recording of a match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub width: f32,
+    pub height: f32,
+    pub tick_rate: f32,
+    /// The input for each tick, in order.
+    pub inputs: Vec<Input>,
+    /// The state hash of the match after the last tick, to check that playback matched.
+    pub final_hash: Option<u64>,
+    /// The match at regular ticks, in order.
+    pub snapshots: Vec<Snapshot>,
+}
+
+/// The state of a match partway through a replay.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// The number of ticks played before the snapshot was taken.
+    pub tick: usize,
+    /// The match, as written by `Simulation::write_state`.
+    pub state: Vec<u8>,
+}
+
+impl Replay {
+    /// Starts recording a match that was created with `Simulation::with_seed`, or
+    /// `Simulation::new`, which picks a seed. Returns `None` for a match without a seed.
+    pub fn new(simulation: &Simulation, tick_rate: f32) -> Option<Replay> {
+        Some(Replay {
+            seed: simulation.seed()?,
+            width: simulation.width(),
+            height: simulation.height(),
+            tick_rate,
+            inputs: Vec::new(),
+            final_hash: None,
+            snapshots: Vec::new(),
+        })
+    }
+
+    /// Adds the input for the next tick, given the match after it was played.
+    pub fn record(&mut self, input: &Input, simulation: &Simulation) {
+        self.inputs.push(*input);
+        if self.inputs.len() % SNAPSHOT_INTERVAL == 0 {
+            let mut state = Vec::new();
+            simulation.write_state(&mut state);
+            self.snapshots.push(Snapshot { tick: self.inputs.len(), state });
+        }
+    }
+
+    /// Notes the state of the match at the end of the recording, so playback can be checked.
+    pub fn finish(&mut self, simulation: &Simulation) {
+        self.final_hash = Some(simulation.state_hash());
+    }
+
+    /// Creates the match as it was when recording started.
+    pub fn simulation(&self) -> Simulation {
+        Simulation::with_seed(self.width, self.height, self.seed)
+    }
+
+    /// Whether a match played back to the end matches the one that was recorded.
+    /// Replays recorded without a final hash can't be checked, and always match.
+    pub fn matches(&self, simulation: &Simulation) -> bool {
+        self.final_hash.map_or(true, |hash| hash == simulation.state_hash())
+    }
+
+    /// Encodes the replay in the file format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        for value in &[self.width, self.height, self.tick_rate] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        match self.final_hash {
+            Some(hash) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&hash.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+
+        write_varint(&mut bytes, self.inputs.len() as u64);
+        let mut i = 0;
+        while i < self.inputs.len() {
+            let input = self.inputs[i];
+            let run = self.inputs[i..].iter().take_while(|&&other| other == input).count();
+            write_varint(&mut bytes, run as u64);
+            write_paddle_input(&mut bytes, &input.left);
+            write_paddle_input(&mut bytes, &input.right);
+            i += run;
+        }
+
+        write_varint(&mut bytes, self.snapshots.len() as u64);
+        for snapshot in &self.snapshots {
+            write_varint(&mut bytes, snapshot.tick as u64);
+            write_varint(&mut bytes, snapshot.state.len() as u64);
+            bytes.extend_from_slice(&snapshot.state);
+        }
+        bytes
+    }
+
+    /// Decodes a replay from the file format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ReplayError::Invalid("not a replay file".to_string()));
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != 1 && version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let seed = u64::from_le_bytes(reader.array()?);
+        let width = reader.f32()?;
+        let height = reader.f32()?;
+        let tick_rate = reader.f32()?;
+        if !(tick_rate.is_finite() && tick_rate > 0.0) {
+            return Err(ReplayError::Invalid(format!("a tick rate of {} isn't possible", tick_rate)));
+        }
+        let final_hash = match reader.byte()? {
+            0 => None,
+            1 => Some(u64::from_le_bytes(reader.array()?)),
+            other => return Err(ReplayError::Invalid(format!("unknown final hash marker {}", other))),
+        };
+
+        let count = reader.varint()?;
+        if count > MAX_TICKS {
+            return Err(ReplayError::Invalid(format!("{} ticks is more than a replay can hold", count)));
+        }
+        let mut inputs = Vec::new();
+        while (inputs.len() as u64) < count {
+            let run = reader.varint()?;
+            if run == 0 || run > count - inputs.len() as u64 {
+                return Err(ReplayError::Invalid(format!("a run of {} ticks doesn't fit", run)));
+            }
+            let input = Input {
+                left: reader.paddle_input()?,
+                right: reader.paddle_input()?,
+            };
+            inputs.extend((0..run).map(|_| input));
+        }
+
+        let mut replay = Replay { seed, width, height, tick_rate, inputs, final_hash, snapshots: Vec::new() };
+        if version == 1 {
+            replay.final_hash = None;
+        } else {
+            let mut simulation = replay.simulation();
+            for _ in 0..reader.varint()? {
+                let tick = reader.varint()?;
+                let length = usize::try_from(reader.varint()?).unwrap_or(usize::MAX);
+                let state = reader.take(length)?.to_vec();
+                let last = replay.snapshots.last().map_or(0, |snapshot| snapshot.tick);
+                if tick <= last as u64 || tick > count {
+                    return Err(ReplayError::Invalid(format!("a snapshot at tick {} is out of order", tick)));
+                }
+                if simulation.read_state(&state).is_none() {
+                    return Err(ReplayError::Invalid(format!("the snapshot at tick {} is damaged", tick)));
+                }
+                replay.snapshots.push(Snapshot { tick: tick as usize, state });
+            }
+        }
+        if !reader.bytes.is_empty() {
+            return Err(ReplayError::Invalid("unexpected data after the end".to_string()));
+        }
+        Ok(replay)
+    }
+
+    /// Saves the replay to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Loads a replay from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        Replay::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// Plays a replay back one tick at a time, and can jump to any tick, using the snapshots to
+/// avoid replaying the match from the start every time.
+#[derive(Clone, Debug)]
+pub struct ReplayViewer {
+    replay: Replay,
+    // The match after each snapshot's number of ticks, in order, starting from tick 0.
+    snapshots: Vec<(usize, Simulation)>,
+    tick: usize,
+}
+
+impl ReplayViewer {
+    /// Prepares to play the replay from the start.
+    /// Replays without snapshots are played through once to take them.
+    pub fn new(replay: Replay) -> ReplayViewer {
+        let start = replay.simulation();
+        let mut snapshots = vec![(0, start.clone())];
+        if replay.snapshots.is_empty() {
+            let mut simulation = start;
+            let params = simulation.update_params(1.0 / replay.tick_rate);
+            for (tick, input) in replay.inputs.iter().enumerate() {
+                simulation.step(&params, input);
+                if (tick + 1) % SNAPSHOT_INTERVAL == 0 {
+                    snapshots.push((tick + 1, simulation.clone()));
+                }
+            }
+        } else {
+            for snapshot in &replay.snapshots {
+                let mut simulation = start.clone();
+                if simulation.read_state(&snapshot.state).is_some() {
+                    snapshots.push((snapshot.tick, simulation));
+                }
+            }
+        }
+        ReplayViewer { replay, snapshots, tick: 0 }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// The match before any ticks have been played.
+    pub fn start(&self) -> Simulation {
+        self.snapshots[0].1.clone()
+    }
+
+    /// The number of ticks that have been played.
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    /// The number of ticks in the replay.
+    pub fn tick_count(&self) -> usize {
+        self.replay.inputs.len()
+    }
+
+    /// Plays the next tick of the replay on `simulation`, which must be at the viewer's tick.
+    /// Returns `false` if the replay is already over.
+    pub fn step(&mut self, simulation: &mut Simulation) -> bool {
+        match self.replay.inputs.get(self.tick) {
+            Some(input) => {
+                let params = simulation.update_params(1.0 / self.replay.tick_rate);
+                simulation.step(&params, input);
+                self.tick += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Puts `simulation` at the given tick, or at the end if the replay is shorter.
+    pub fn seek(&mut self, simulation: &mut Simulation, tick: usize) {
+        let tick = tick.min(self.tick_count());
+        let i = self.snapshots.partition_point(|&(snapshot_tick, _)| snapshot_tick <= tick) - 1;
+        let (snapshot_tick, ref snapshot) = self.snapshots[i];
+        *simulation = snapshot.clone();
+        self.tick = snapshot_tick;
+        while self.tick < tick {
+            self.step(simulation);
+        }
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_paddle_input(bytes: &mut Vec<u8>, input: &PaddleInput) {
+    let mut flags = 0;
+    for &(set, flag) in &[
+        (input.up, FLAG_UP),
+        (input.down, FLAG_DOWN),
+        (input.serve, FLAG_SERVE),
+        (input.axis != 0.0, FLAG_AXIS),
+        (input.target_y.is_some(), FLAG_TARGET_Y),
+    ] {
+        if set {
+            flags |= flag;
+        }
+    }
+    bytes.push(flags);
+    if input.axis != 0.0 {
+        bytes.extend_from_slice(&input.axis.to_le_bytes());
+    }
+    if let Some(target_y) = input.target_y {
+        bytes.extend_from_slice(&target_y.to_le_bytes());
+    }
+}
+
+/// Reads values from the front of a replay file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() < count {
+            return Err(ReplayError::Invalid("the file ends too soon".to_string()));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn byte(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn f32(&mut self) -> Result<f32, ReplayError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplayError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ReplayError::Invalid("a count is too long".to_string()))
+    }
+
+    fn paddle_input(&mut self) -> Result<PaddleInput, ReplayError> {
+        let flags = self.byte()?;
+        if flags >= FLAG_TARGET_Y << 1 {
+            return Err(ReplayError::Invalid(format!("unknown input flags {:#x}", flags)));
+        }
+        Ok(PaddleInput {
+            up: flags & FLAG_UP != 0,
+            down: flags & FLAG_DOWN != 0,
+            serve: flags & FLAG_SERVE != 0,
+            axis: if flags & FLAG_AXIS != 0 { self.f32()? } else { 0.0 },
+            target_y: if flags & FLAG_TARGET_Y != 0 { Some(self.f32()?) } else { None },
+        })
+    }
+}
+
+/// Something wrong with a replay file.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// The file was written by a version of the game that this one can't read.
+    UnsupportedVersion(u16),
+    /// The file isn't a replay, or is damaged.
+    Invalid(String),
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> ReplayError {
+        ReplayError::Io(err)
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref err) => err.fmt(f),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "replay version {} isn't supported, only version {}", version, REPLAY_VERSION)
+            }
+            ReplayError::Invalid(ref message) => write!(f, "invalid replay: {}", message),
+        }
+    }
+}
+
+impl StdError for ReplayError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            ReplayError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {AiController, Difficulty, GameRng, Side};
+    use {GAME_HEIGHT, GAME_WIDTH};
+
+    /// Records a match between two AIs.
+    fn record(seed: u64, ticks: u32) -> (Replay, Simulation) {
+        let mut sim = Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, seed);
+        let mut replay = Replay::new(&sim, 60.0).unwrap();
+        let settings = Difficulty::Medium.settings();
+        let mut left = AiController::with_rng(Side::Left, settings, GameRng::from_seed(seed));
+        let mut right = AiController::with_rng(Side::Right, settings, GameRng::from_seed(seed + 1));
+        let params = sim.update_params(1.0 / 60.0);
+        for tick in 0..ticks {
+            let input = Input {
+                left: PaddleInput { serve: tick == 30, ..left.update(&sim, params.dt) },
+                right: PaddleInput { target_y: Some(200.0), ..right.update(&sim, params.dt) },
+            };
+            sim.step(&params, &input);
+            replay.record(&input, &sim);
+        }
+        replay.finish(&sim);
+        (replay, sim)
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let (replay, _) = record(3, 2000);
+        assert_eq!(Replay::from_bytes(&replay.to_bytes()).unwrap(), replay);
+    }
+
+    #[test]
+    fn playback_reproduces_the_match() {
+        let (replay, recorded) = record(5, 5000);
+        let replay = Replay::from_bytes(&replay.to_bytes()).unwrap();
+        let mut sim = replay.simulation();
+        let params = sim.update_params(1.0 / replay.tick_rate);
+        for input in &replay.inputs {
+            sim.step(&params, input);
+        }
+        assert_eq!(sim.state_hash(), recorded.state_hash());
+        assert!(replay.matches(&sim));
+        sim.step(&params, &Input::default());
+        assert!(!replay.matches(&sim));
+    }
+
+    #[test]
+    fn repeated_input_is_compact() {
+        let sim = Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, 1);
+        let mut replay = Replay::new(&sim, 60.0).unwrap();
+        let held = Input { left: PaddleInput { up: true, ..PaddleInput::default() }, ..Input::default() };
+        for _ in 0..10_000 {
+            replay.record(&held, &sim);
+        }
+        replay.snapshots.clear();
+        assert!(replay.to_bytes().len() < 40);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let bytes = record(1, 100).0.to_bytes();
+        match Replay::from_bytes(b"PNG\x89 not a replay") {
+            Err(ReplayError::Invalid(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        match Replay::from_bytes(&bytes[..bytes.len() - 1]) {
+            Err(ReplayError::Invalid(message)) => assert!(message.contains("ends too soon")),
+            other => panic!("{:?}", other),
+        }
+        let mut future = bytes.clone();
+        future[4] = 99;
+        match Replay::from_bytes(&future) {
+            Err(ReplayError::UnsupportedVersion(99)) => (),
+            other => panic!("{:?}", other),
+        }
+
+        // The tick rate comes after the magic, version, seed, width and height.
+        for &tick_rate in &[0.0, -60.0, f32::INFINITY, f32::NAN] {
+            let mut bad = bytes.clone();
+            bad[22..26].copy_from_slice(&f32::to_le_bytes(tick_rate));
+            match Replay::from_bytes(&bad) {
+                Err(ReplayError::Invalid(message)) => assert!(message.contains("tick rate"), "{}", message),
+                other => panic!("{:?}", other),
+            }
+        }
+
+        // A huge tick count is rejected before anything is allocated for it.
+        let mut huge = bytes[..26].to_vec();
+        huge.push(0);
+        write_varint(&mut huge, u64::MAX);
+        write_varint(&mut huge, u64::MAX);
+        huge.extend_from_slice(&[0, 0]);
+        match Replay::from_bytes(&huge) {
+            Err(ReplayError::Invalid(message)) => assert!(message.contains("more than a replay can hold"), "{}", message),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_version_1_files() {
+        let (mut replay, _) = record(2, 1000);
+        replay.snapshots.clear();
+        let mut bytes = replay.to_bytes();
+        bytes.truncate(bytes.len() - 1);
+        bytes[4] = 1;
+        let old = Replay::from_bytes(&bytes).unwrap();
+        assert_eq!(old.inputs, replay.inputs);
+        assert_eq!(old.final_hash, None);
+    }
+
+    #[test]
+    fn records_snapshots_at_intervals() {
+        let (replay, _) = record(4, SNAPSHOT_INTERVAL as u32 * 3 + 10);
+        let ticks: Vec<usize> = replay.snapshots.iter().map(|snapshot| snapshot.tick).collect();
+        assert_eq!(ticks, vec![SNAPSHOT_INTERVAL, SNAPSHOT_INTERVAL * 2, SNAPSHOT_INTERVAL * 3]);
+    }
+
+    #[test]
+    fn viewer_seeks_to_the_same_state_as_playing() {
+        let (replay, recorded) = record(6, 2000);
+        let mut without_snapshots = replay.clone();
+        without_snapshots.snapshots.clear();
+
+        for replay in [replay, without_snapshots] {
+            let mut viewer = ReplayViewer::new(replay);
+            let mut sim = viewer.start();
+            let mut played = viewer.start();
+            let mut hashes = Vec::new();
+            while viewer.step(&mut played) {
+                hashes.push(played.state_hash());
+            }
+            assert_eq!(played.state_hash(), recorded.state_hash());
+
+            for &tick in &[1234, 5, 1999, 600, 2000] {
+                viewer.seek(&mut sim, tick);
+                assert_eq!(viewer.tick(), tick);
+                assert_eq!(sim.state_hash(), hashes[tick - 1]);
+            }
+            viewer.seek(&mut sim, 5000);
+            assert_eq!(viewer.tick(), 2000);
+            viewer.seek(&mut sim, 0);
+            assert_eq!(sim.state_hash(), viewer.start().state_hash());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! The random number generator behind serves and the AI, which can be seeded so that a match
+//! plays out the same way every time.
+
+use rand::{self, Rng};
+
+/// A fast random number generator that is either seeded or started from system randomness.
+///
+/// This is the xorshift128 generator, the same as rand's `XorShiftRng`, but with its state
+/// exposed so that it can be saved in replay snapshots and restored exactly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRng([u32; 4]);
+
+impl GameRng {
+    /// Creates a generator that produces the same numbers for the same seed.
+    pub fn from_seed(seed: u64) -> GameRng {
+        // Spread the seed over the generator's whole state.
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        let (a, b) = (next(), next());
+        GameRng::from_state([a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32])
+    }
+
+    /// Creates a generator that produces different numbers every time.
+    pub fn from_entropy() -> GameRng {
+        GameRng::from_state(rand::weak_rng().gen())
+    }
+
+    /// Restores a generator to a state returned by `state`.
+    pub fn from_state(mut state: [u32; 4]) -> GameRng {
+        // The generator would only ever produce zeros from a state of all zeros.
+        if state == [0; 4] {
+            state[0] = 1;
+        }
+        GameRng(state)
+    }
+
+    /// The generator's current state, from which it can be restored with `from_state`.
+    pub fn state(&self) -> [u32; 4] {
+        self.0
+    }
+}
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        let [x, y, z, w] = self.0;
+        let t = x ^ (x << 11);
+        let next = w ^ (w >> 19) ^ (t ^ (t >> 8));
+        self.0 = [y, z, w, next];
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    #[test]
+    fn same_seed_gives_same_numbers() {
+        let mut a = GameRng::from_seed(42);
+        let mut b = GameRng::from_seed(42);
+        let mut c = GameRng::from_seed(43);
+        let a: Vec<u32> = (0..8).map(|_| a.next_u32()).collect();
+        let b: Vec<u32> = (0..8).map(|_| b.next_u32()).collect();
+        let c: Vec<u32> = (0..8).map(|_| c.next_u32()).collect();
+        assert_eq!(a, b);
+        assert!(a != c);
+    }
+
+    #[test]
+    fn matches_xorshift_and_restores_state() {
+        let mut rng = GameRng::from_seed(7);
+        let mut expected = XorShiftRng::from_seed(rng.state());
+        for _ in 0..100 {
+            assert_eq!(rng.next_u32(), expected.next_u32());
+        }
+
+        let mut restored = GameRng::from_state(rng.state());
+        assert_eq!(restored.next_u64(), rng.next_u64());
+    }
+}
@@ -0,0 +1,361 @@
+//! Online play between two copies of the game, where each runs the whole match.
+//!
+//! Every tick, each peer sends the input for its own paddle, and carries on without waiting for
+//! the other's by predicting that the other paddle keeps doing whatever it was last seen doing.
+//! When the real input for a tick arrives and turns out to be different, the peer rolls the match
+//! back to its snapshot from before that tick and plays it forward again with the right input.
+//! As long as every input gets through in the end, both peers play out exactly the same match.
+//!
+//! Inputs are sent again in every packet until the other peer says it has them, so a lost packet
+//! only delays them. Local input takes effect a couple of ticks after it is read, which gives it
+//! time to reach the other peer before it is needed there and so saves most rollbacks.
+//! A peer that gets too far ahead of what it has heard from the other waits for it to catch up.
+
+use net::{Link, NetError, Packet, MAX_INPUTS_PER_PACKET, POLL_INTERVAL, PROTOCOL_VERSION, RETRY_INTERVAL, TIMEOUT};
+use rand::Rng;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Instant;
+use {GameRng, Input, InputMap, PaddleInput, Side, Simulation, GAME_HEIGHT, GAME_WIDTH};
+
+/// How many ticks after it is read that local input takes effect, unless changed with
+/// `set_input_delay`.
+pub const DEFAULT_INPUT_DELAY: usize = 2;
+
+/// The most ticks that are played ahead of the other peer's input before waiting for it.
+pub const MAX_ROLLBACK: usize = 15;
+
+/// One peer's side of an online match.
+pub struct RollbackSession {
+    link: Link,
+    peer: SocketAddr,
+    side: Side,
+    tick_rate: f32,
+    input_delay: usize,
+
+    // The match after `tick` ticks, some of which may have been played with predicted input.
+    simulation: Simulation,
+    tick: usize,
+
+    // The input for the local paddle, by tick, including the ticks it is delayed by, and how
+    // many of them the other peer has.
+    local_inputs: Vec<PaddleInput>,
+    acked: usize,
+
+    // The input for the other paddle as far as it has arrived, by tick.
+    remote_inputs: Vec<PaddleInput>,
+
+    // The number of ticks played with the other paddle's real input. For each tick after that,
+    // the state of the match before it and the input predicted for the other paddle.
+    verified: usize,
+    snapshots: VecDeque<Vec<u8>>,
+    predictions: VecDeque<PaddleInput>,
+
+    last_heard: Instant,
+    rollbacks: u64,
+}
+
+impl RollbackSession {
+    /// Waits for a player to join on the link, then starts a match against them with this peer
+    /// on the left.
+    pub fn host(mut link: Link, tick_rate: f32) -> Result<RollbackSession, NetError> {
+        let seed = GameRng::from_entropy().gen();
+        loop {
+            match link.recv_from()? {
+                Some((Packet::Hello { .. }, addr)) => {
+                    // A player with another version learns of the mismatch from the answer.
+                    let welcome = Packet::Welcome { version: PROTOCOL_VERSION, seed, tick_rate };
+                    link.send_to(&welcome, addr)?;
+                    return Ok(RollbackSession::new(link, addr, Side::Left, seed, tick_rate));
+                }
+                Some(_) => (),
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Joins the match hosted at the address, playing on the right.
+    pub fn join<A: ToSocketAddrs>(mut link: Link, addr: A) -> Result<RollbackSession, NetError> {
+        let host = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to join"))?;
+        let start = Instant::now();
+        let mut last_hello = None;
+        while start.elapsed() < TIMEOUT {
+            if last_hello.map_or(true, |time: Instant| time.elapsed() >= RETRY_INTERVAL) {
+                link.send_to(&Packet::Hello { version: PROTOCOL_VERSION }, host)?;
+                last_hello = Some(Instant::now());
+            }
+            match link.recv_from()? {
+                Some((Packet::Welcome { version, seed, tick_rate }, addr)) if addr == host => {
+                    if version != PROTOCOL_VERSION {
+                        return Err(NetError::VersionMismatch(version));
+                    }
+                    return Ok(RollbackSession::new(link, host, Side::Right, seed, tick_rate));
+                }
+                Some(_) => (),
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+        Err(NetError::TimedOut)
+    }
+
+    fn new(link: Link, peer: SocketAddr, side: Side, seed: u64, tick_rate: f32) -> RollbackSession {
+        RollbackSession {
+            link,
+            peer,
+            side,
+            tick_rate,
+            input_delay: DEFAULT_INPUT_DELAY,
+            simulation: Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, seed),
+            tick: 0,
+            local_inputs: vec![PaddleInput::default(); DEFAULT_INPUT_DELAY],
+            acked: 0,
+            remote_inputs: Vec::new(),
+            verified: 0,
+            snapshots: VecDeque::new(),
+            predictions: VecDeque::new(),
+            last_heard: Instant::now(),
+            rollbacks: 0,
+        }
+    }
+
+    /// Changes how many ticks local input is delayed by, which can only be done before the
+    /// match starts. Both peers must use the same delay for the match to start fairly.
+    pub fn set_input_delay(&mut self, input_delay: usize) {
+        if self.local_inputs.len() == self.input_delay {
+            self.input_delay = input_delay;
+            self.local_inputs = vec![PaddleInput::default(); input_delay];
+        }
+    }
+
+    /// Gives the paddles the bindings for the players, so that the simulation can read input.
+    pub fn set_input_map(&mut self, input_map: &InputMap) {
+        self.simulation.set_input_map(input_map);
+    }
+
+    /// The side of the court that this peer plays.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// The tick rate that both peers play at, which is the host's.
+    pub fn tick_rate(&self) -> f32 {
+        self.tick_rate
+    }
+
+    /// The match as this peer currently sees it.
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    /// The number of ticks that have been played.
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    /// Whether every tick so far has been played with the other paddle's real input.
+    pub fn is_verified(&self) -> bool {
+        self.verified == self.tick
+    }
+
+    /// The number of times the match has been rolled back to correct a prediction.
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    /// Plays the next tick with the given input for the local paddle.
+    /// Returns `false` without playing it if this peer is too far ahead of the other.
+    pub fn advance(&mut self, input: PaddleInput) -> Result<bool, NetError> {
+        self.receive()?;
+        let ahead = self.tick >= self.remote_inputs.len() + MAX_ROLLBACK;
+        if !ahead {
+            self.local_inputs.push(input);
+            self.play_tick();
+        }
+        self.send_inputs()?;
+        Ok(!ahead)
+    }
+
+    /// Takes in any packets from the other peer, rolling back if a prediction was wrong, and
+    /// sends it any inputs it is missing.
+    pub fn poll(&mut self) -> Result<(), NetError> {
+        self.receive()?;
+        self.send_inputs()
+    }
+
+    fn receive(&mut self) -> Result<(), NetError> {
+        while let Some((packet, addr)) = self.link.recv_from()? {
+            if addr != self.peer {
+                continue;
+            }
+            self.last_heard = Instant::now();
+            match packet {
+                Packet::Hello { .. } => {
+                    // The welcome was lost, so send it again.
+                    let welcome = Packet::Welcome {
+                        version: PROTOCOL_VERSION,
+                        seed: self.simulation.seed().unwrap_or(0),
+                        tick_rate: self.tick_rate,
+                    };
+                    self.link.send_to(&welcome, addr)?;
+                }
+                Packet::Inputs { ack, start, inputs } => {
+                    self.acked = self.acked.max(ack as usize).min(self.local_inputs.len());
+                    let skip = self.remote_inputs.len().saturating_sub(start as usize);
+                    if start as usize <= self.remote_inputs.len() && skip < inputs.len() {
+                        self.remote_inputs.extend_from_slice(&inputs[skip..]);
+                    }
+                }
+                _ => (),
+            }
+        }
+        if self.last_heard.elapsed() > TIMEOUT {
+            return Err(NetError::TimedOut);
+        }
+
+        self.reconcile();
+        Ok(())
+    }
+
+    /// Checks the predictions against the input that has arrived, and plays the match again from
+    /// the first one that was wrong.
+    fn reconcile(&mut self) {
+        while self.verified < self.tick && self.verified < self.remote_inputs.len() {
+            if self.predictions[0] != self.remote_inputs[self.verified] {
+                let end = self.tick;
+                let snapshot = self.snapshots.pop_front().unwrap();
+                self.simulation
+                    .read_state(&snapshot)
+                    .expect("snapshots of the match can always be read back");
+                self.tick = self.verified;
+                self.snapshots.clear();
+                self.predictions.clear();
+                while self.tick < end {
+                    self.play_tick();
+                }
+                self.rollbacks += 1;
+                return;
+            }
+            self.snapshots.pop_front();
+            self.predictions.pop_front();
+            self.verified += 1;
+        }
+    }
+
+    /// Plays the next tick, predicting the other paddle's input if it hasn't arrived.
+    fn play_tick(&mut self) {
+        let remote = match self.remote_inputs.get(self.tick) {
+            Some(&input) => {
+                self.verified += 1;
+                input
+            }
+            None => {
+                let predicted = self.remote_inputs.last().cloned().unwrap_or_default();
+                let mut snapshot = Vec::new();
+                self.simulation.write_state(&mut snapshot);
+                self.snapshots.push_back(snapshot);
+                self.predictions.push_back(predicted);
+                predicted
+            }
+        };
+
+        let mut input = Input::default();
+        *input.paddle_mut(self.side) = self.local_inputs[self.tick];
+        *input.paddle_mut(self.side.opposite()) = remote;
+        let params = self.simulation.update_params(1.0 / self.tick_rate);
+        self.simulation.step(&params, &input);
+        self.tick += 1;
+    }
+
+    fn send_inputs(&mut self) -> Result<(), NetError> {
+        let end = self.local_inputs.len().min(self.acked + MAX_INPUTS_PER_PACKET);
+        let packet = Packet::Inputs {
+            ack: self.remote_inputs.len() as u32,
+            start: self.acked as u32,
+            inputs: self.local_inputs[self.acked..end].to_vec(),
+        };
+        self.link.send_to(&packet, self.peer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use net::NetConditions;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // Changes what each paddle does every so often, so that predictions are often wrong.
+    fn scripted_input(side: Side, tick: usize) -> PaddleInput {
+        let phase = (tick / 20 + if side == Side::Left { 0 } else { 1 }) % 3;
+        PaddleInput {
+            up: phase == 0,
+            down: phase == 1,
+            serve: tick % 50 == 0,
+            ..PaddleInput::default()
+        }
+    }
+
+    #[test]
+    fn peers_agree_despite_latency_and_loss() {
+        let conditions = NetConditions {
+            latency: Duration::from_millis(20),
+            jitter: Duration::from_millis(10),
+            loss: 0.2,
+        };
+        let mut host_link = Link::bind("127.0.0.1:0").unwrap();
+        let mut join_link = Link::bind("127.0.0.1:0").unwrap();
+        host_link.set_conditions(conditions);
+        join_link.set_conditions(conditions);
+        let addr = host_link.local_addr().unwrap();
+
+        // Each peer plays in its own thread, and keeps answering the other until both are done.
+        const TICKS: usize = 300;
+        let done = Arc::new(AtomicUsize::new(0));
+        let play = |done: Arc<AtomicUsize>, mut peer: RollbackSession| {
+            let start = Instant::now();
+            let mut finished = false;
+            while done.load(Ordering::SeqCst) < 2 {
+                assert!(start.elapsed() < Duration::from_secs(20), "the peers never caught up");
+                if peer.tick() < TICKS {
+                    let input = scripted_input(peer.side(), peer.tick());
+                    peer.advance(input).unwrap();
+                } else {
+                    peer.poll().unwrap();
+                }
+                if !finished && peer.tick() == TICKS && peer.is_verified() {
+                    finished = true;
+                    done.fetch_add(1, Ordering::SeqCst);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            peer
+        };
+        let host_done = done.clone();
+        let host = thread::spawn(move || play(host_done, RollbackSession::host(host_link, 60.0).unwrap()));
+        let right = play(done, RollbackSession::join(join_link, addr).unwrap());
+        let peers = [host.join().unwrap(), right];
+
+        // Both peers should match a match played offline with the same inputs.
+        let seed = peers[0].simulation().seed().unwrap();
+        let mut offline = Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, seed);
+        let params = offline.update_params(1.0 / 60.0);
+        for tick in 0..TICKS {
+            let mut input = Input::default();
+            if tick >= DEFAULT_INPUT_DELAY {
+                input.left = scripted_input(Side::Left, tick - DEFAULT_INPUT_DELAY);
+                input.right = scripted_input(Side::Right, tick - DEFAULT_INPUT_DELAY);
+            }
+            offline.step(&params, &input);
+        }
+        assert_eq!(peers[0].simulation().state_hash(), offline.state_hash());
+        assert_eq!(peers[1].simulation().state_hash(), offline.state_hash());
+        assert!(peers.iter().any(|peer| peer.rollbacks() > 0));
+    }
+}
@@ -0,0 +1,357 @@
+//! A dedicated server that plays the match itself, for players and spectators to connect to.
+//!
+//! The server has the final say on how the match plays out. Each player sends the input for its
+//! paddle every tick, and the server plays them one per tick in the order they were sent. After
+//! every tick it sends everyone a snapshot of the whole match, telling each player how many of
+//! its inputs have been played so that it can predict the rest; see the client module.
+//!
+//! The first two players to join get the left and right paddles, and everyone else spectates.
+//! The match only runs while both paddles have players, and a new one is set up when both leave.
+//!
+//! Spectators can join at any time. They are sent a snapshot to start from, and after that only
+//! deltas from the latest tick they have said they are up to, which take much less bandwidth.
+//! They can be kept some time behind the match with `set_spectator_delay`, such as to stop
+//! anyone watching from helping the players.
+
+use net::{Link, NetError, Packet, DELTA_HISTORY, POLL_INTERVAL, PROTOCOL_VERSION, TIMEOUT};
+use state;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+use {FixedTimestep, Input, PaddleInput, Side, Simulation, GAME_HEIGHT, GAME_WIDTH};
+
+// The most inputs from a player that can wait to be played. Older ones are skipped, so that a
+// player whose packets arrive in a burst doesn't stay behind for the rest of the match.
+const MAX_QUEUED_INPUTS: usize = 8;
+
+// The most ticks to play at once to catch up after the server was held up.
+const MAX_TICKS_PER_POLL: u32 = 10;
+
+/// A player or spectator connected to the server.
+struct Connection {
+    addr: SocketAddr,
+    side: Option<Side>,
+    last_heard: Instant,
+
+    // The latest tick that a spectator has said it is up to.
+    seen: Option<usize>,
+
+    // The player's inputs that have arrived but not been played yet, how many have arrived,
+    // how many have been played or skipped, and the last one played, which is played again if
+    // the next hasn't arrived in time.
+    queued: VecDeque<PaddleInput>,
+    received: usize,
+    played: usize,
+    last_input: PaddleInput,
+}
+
+impl Connection {
+    fn new(addr: SocketAddr, side: Option<Side>) -> Connection {
+        Connection {
+            addr,
+            side,
+            last_heard: Instant::now(),
+            seen: None,
+            queued: VecDeque::new(),
+            received: 0,
+            played: 0,
+            last_input: PaddleInput::default(),
+        }
+    }
+
+    /// Takes the inputs from a packet that haven't arrived before.
+    fn receive_inputs(&mut self, start: usize, inputs: &[PaddleInput]) {
+        let skip = self.received.saturating_sub(start);
+        if start > self.received || skip >= inputs.len() {
+            return;
+        }
+        self.queued.extend(&inputs[skip..]);
+        self.received += inputs.len() - skip;
+        while self.queued.len() > MAX_QUEUED_INPUTS {
+            self.queued.pop_front();
+            self.played += 1;
+        }
+    }
+
+    /// The input to play this tick.
+    fn next_input(&mut self) -> PaddleInput {
+        if let Some(input) = self.queued.pop_front() {
+            self.last_input = input;
+            self.played += 1;
+        }
+        self.last_input
+    }
+}
+
+/// Runs matches between the players connected over a link.
+pub struct Server {
+    link: Link,
+    tick_rate: f32,
+    simulation: Simulation,
+    tick: usize,
+    connections: Vec<Connection>,
+
+    // How many ticks spectators are kept behind the match, and the state of the match after each
+    // of the latest ticks, in order, going back far enough to send spectators deltas.
+    spectator_delay: usize,
+    history: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl Server {
+    /// Creates a server that listens on the link and plays at the given tick rate.
+    pub fn new(link: Link, tick_rate: f32) -> Server {
+        Server {
+            link,
+            tick_rate,
+            simulation: Simulation::new(GAME_WIDTH as f32, GAME_HEIGHT as f32),
+            tick: 0,
+            connections: Vec::new(),
+            spectator_delay: 0,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Keeps spectators the given time behind the match.
+    pub fn set_spectator_delay(&mut self, delay: Duration) {
+        self.spectator_delay = (delay.as_secs_f32() * self.tick_rate).round() as usize;
+    }
+
+    /// The match being played.
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    /// The number of ticks of the match that have been played.
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    /// The number of players connected, which is at most two.
+    pub fn players(&self) -> usize {
+        self.connections.iter().filter(|connection| connection.side.is_some()).count()
+    }
+
+    /// The number of spectators connected.
+    pub fn spectators(&self) -> usize {
+        self.connections.len() - self.players()
+    }
+
+    /// Serves matches in real time until an error occurs.
+    pub fn run(&mut self) -> Result<(), NetError> {
+        self.run_while(|_| true)
+    }
+
+    /// Serves matches in real time for as long as `keep_running` says to.
+    pub fn run_while<F: FnMut(&Server) -> bool>(&mut self, mut keep_running: F) -> Result<(), NetError> {
+        let mut timestep = FixedTimestep::new(self.tick_rate, MAX_TICKS_PER_POLL);
+        let mut last_poll = Instant::now();
+        while keep_running(self) {
+            self.poll()?;
+            let now = Instant::now();
+            for _ in 0..timestep.advance(now - last_poll) {
+                self.update()?;
+            }
+            last_poll = now;
+            thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Takes in any packets from clients, accepting new ones, and forgets any that have gone quiet.
+    pub fn poll(&mut self) -> Result<(), NetError> {
+        while let Some((packet, addr)) = self.link.recv_from()? {
+            let known = self.connections.iter().position(|connection| connection.addr == addr);
+            match (packet, known) {
+                (Packet::Join { version, spectate }, None) => {
+                    // Clients with another version learn of the mismatch from the answer,
+                    // and aren't let in.
+                    let side = if version != PROTOCOL_VERSION || spectate { None } else { self.free_side() };
+                    if version == PROTOCOL_VERSION {
+                        self.connections.push(Connection::new(addr, side));
+                    }
+                    self.accept(addr, side)?;
+                }
+                (Packet::Join { .. }, Some(i)) => {
+                    // The answer was lost, so send it again.
+                    self.connections[i].last_heard = Instant::now();
+                    let side = self.connections[i].side;
+                    self.accept(addr, side)?;
+                }
+                (Packet::Inputs { ack, start, ref inputs }, Some(i)) => {
+                    let connection = &mut self.connections[i];
+                    connection.last_heard = Instant::now();
+                    if connection.side.is_some() {
+                        connection.receive_inputs(start as usize, inputs);
+                    } else {
+                        connection.seen = Some(ack as usize);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        self.connections.retain(|connection| connection.last_heard.elapsed() <= TIMEOUT);
+        if self.players() == 0 && self.tick > 0 {
+            self.simulation = Simulation::new(GAME_WIDTH as f32, GAME_HEIGHT as f32);
+            self.tick = 0;
+            // Spectators start the new match from a snapshot.
+            self.history.clear();
+            for connection in &mut self.connections {
+                connection.seen = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays a tick if both paddles have players, and sends everyone a snapshot of the match.
+    pub fn update(&mut self) -> Result<(), NetError> {
+        if self.players() == 2 {
+            let mut input = Input::default();
+            for connection in &mut self.connections {
+                if let Some(side) = connection.side {
+                    *input.paddle_mut(side) = connection.next_input();
+                }
+            }
+            let params = self.simulation.update_params(1.0 / self.tick_rate);
+            self.simulation.step(&params, &input);
+            self.tick += 1;
+        } else {
+            // Nothing sent while waiting for a player is played.
+            for connection in &mut self.connections {
+                connection.played += connection.queued.len();
+                connection.queued.clear();
+            }
+        }
+
+        if self.history.back().map_or(true, |&(tick, _)| tick != self.tick) {
+            let mut state = Vec::new();
+            self.simulation.write_state(&mut state);
+            self.history.push_back((self.tick, state));
+            if self.history.len() > self.spectator_delay.saturating_add(DELTA_HISTORY) {
+                self.history.pop_front();
+            }
+        }
+
+        // Players see the match as it is, and spectators as it was the delay ago, or as it started.
+        let (tick, ref state) = *self.history.back().unwrap();
+        let spectator_tick = self.tick.saturating_sub(self.spectator_delay).max(self.history[0].0);
+        for connection in &self.connections {
+            let packet = if connection.side.is_some() {
+                Packet::Snapshot { match_id: self.match_id(), tick: tick as u32, ack: connection.played as u32, state: state.clone() }
+            } else {
+                self.spectator_packet(spectator_tick, connection.seen)
+            };
+            self.link.send_to(&packet, connection.addr)?;
+        }
+        Ok(())
+    }
+
+    /// The state of the match after the given tick, if it is still in the history.
+    fn state_after(&self, tick: usize) -> Option<&Vec<u8>> {
+        let first = self.history.front()?.0;
+        let i = tick.checked_sub(first)?;
+        self.history.get(i).map(|(_, state)| state)
+    }
+
+    /// Sends a spectator the match after the tick, as a delta from the tick it has seen if that
+    /// is still in the history, or else as a snapshot.
+    fn spectator_packet(&self, tick: usize, seen: Option<usize>) -> Packet {
+        let state = self.state_after(tick).unwrap();
+        let base = seen.filter(|&seen| seen <= tick).and_then(|seen| self.state_after(seen).map(|base| (seen, base)));
+        match base {
+            Some((seen, base)) if base.len() == state.len() => {
+                let mut delta = Vec::new();
+                state::write_delta(&mut delta, base, state);
+                Packet::Delta { match_id: self.match_id(), tick: tick as u32, base: seen as u32, delta }
+            }
+            _ => Packet::Snapshot { match_id: self.match_id(), tick: tick as u32, ack: 0, state: state.clone() },
+        }
+    }
+
+    /// The seed of the match being played, which changes with each new match.
+    fn match_id(&self) -> u64 {
+        self.simulation.seed().expect("server matches are always seeded")
+    }
+
+    /// The first paddle without a player.
+    fn free_side(&self) -> Option<Side> {
+        [Side::Left, Side::Right]
+            .iter()
+            .cloned()
+            .find(|&side| self.connections.iter().all(|connection| connection.side != Some(side)))
+    }
+
+    fn accept(&mut self, addr: SocketAddr, side: Option<Side>) -> Result<(), NetError> {
+        let accept = Packet::Accept { version: PROTOCOL_VERSION, side, tick_rate: self.tick_rate };
+        self.link.send_to(&accept, addr)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive(link: &mut Link) -> Packet {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some((packet, _)) = link.recv_from().unwrap() {
+                return packet;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        panic!("nothing arrived");
+    }
+
+    fn join(server: &mut Server, spectate: bool) -> Link {
+        let mut link = Link::bind("127.0.0.1:0").unwrap();
+        let addr = server.link.local_addr().unwrap();
+        link.send_to(&Packet::Join { version: PROTOCOL_VERSION, spectate }, addr).unwrap();
+        while server.connections.iter().all(|connection| connection.addr != link.local_addr().unwrap()) {
+            server.poll().unwrap();
+            thread::sleep(POLL_INTERVAL);
+        }
+        match receive(&mut link) {
+            Packet::Accept { side, .. } => assert_eq!(side.is_none(), spectate),
+            other => panic!("{:?}", other),
+        }
+        link
+    }
+
+    #[test]
+    fn late_spectators_get_a_snapshot_then_deltas_behind_the_match() {
+        let mut server = Server::new(Link::bind("127.0.0.1:0").unwrap(), 60.0);
+        server.set_spectator_delay(Duration::from_millis(500));
+        let addr = server.link.local_addr().unwrap();
+        let _left = join(&mut server, false);
+        let _right = join(&mut server, false);
+        for _ in 0..100 {
+            server.update().unwrap();
+        }
+
+        let mut spectator = join(&mut server, true);
+        assert_eq!((server.players(), server.spectators()), (2, 1));
+        server.update().unwrap();
+        let state = match receive(&mut spectator) {
+            Packet::Snapshot { match_id, tick: 71, state, .. } if match_id == server.match_id() => state,
+            other => panic!("{:?}", other),
+        };
+
+        // Once the spectator says where it is up to, it only gets what changed since then.
+        let inputs = Packet::Inputs { ack: 71, start: 0, inputs: Vec::new() };
+        spectator.send_to(&inputs, addr).unwrap();
+        while server.connections[2].seen.is_none() {
+            server.poll().unwrap();
+            thread::sleep(POLL_INTERVAL);
+        }
+        server.update().unwrap();
+        let delta = match receive(&mut spectator) {
+            Packet::Delta { match_id, tick: 72, base: 71, delta } if match_id == server.match_id() => delta,
+            other => panic!("{:?}", other),
+        };
+        assert!(delta.len() < state.len());
+        let state = state::read_delta(&mut &delta[..], &state).unwrap();
+        assert_eq!(Some(&state), server.state_after(72));
+    }
+}
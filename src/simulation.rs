@@ -0,0 +1,394 @@
+//! The state of a match of Pong, without any windowing or rendering.
+
+use {Ball, Bindings, GameRng, InputMap, InputState, PaddleInput, Paddle, ServeSettings, Side, UpdateParams};
+use rand::Rng;
+use state;
+use std::hash::Hasher;
+use SCORE_TO_WIN;
+
+/// A snapshot of the player input for both paddles during one tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Input {
+    pub left: PaddleInput,
+    pub right: PaddleInput,
+}
+
+impl Input {
+    /// Returns the input for the paddle on the given side.
+    pub fn paddle(&self, side: Side) -> &PaddleInput {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    /// Returns the input for the paddle on the given side.
+    pub fn paddle_mut(&mut self, side: Side) -> &mut PaddleInput {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+}
+
+/// The paddles and ball for a single match.
+///
+/// All randomness in a match comes from a generator seeded when it is created, and updates
+/// only do plain arithmetic, so the same seed and inputs give bit-for-bit the same match on
+/// the same platform. `state_hash` can be compared to check that two matches are in step.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    width: f32,
+    height: f32,
+    seed: Option<u64>,
+
+    left_paddle: Paddle,
+    right_paddle: Paddle,
+
+    ball: Ball,
+}
+
+impl Simulation {
+    /// Creates a new match on a court of the given size, with the ball waiting to be served.
+    /// The match is seeded randomly; see `seed` to play it again.
+    pub fn new(width: f32, height: f32) -> Simulation {
+        Simulation::with_seed(width, height, GameRng::from_entropy().next_u64())
+    }
+
+    /// Creates a new match whose serves are chosen by a generator seeded with `seed`,
+    /// so that the same seed and inputs always play out the same way.
+    pub fn with_seed(width: f32, height: f32, seed: u64) -> Simulation {
+        let ball = Ball::with_rng(width / 2.0, height / 2.0, ServeSettings::default(), GameRng::from_seed(seed));
+        Simulation {
+            seed: Some(seed),
+            ..Simulation::with_ball(width, height, ball)
+        }
+    }
+
+    /// Creates a new match using the given ball.
+    /// The left paddle is controlled with W and S, and the right paddle with the arrow keys.
+    /// Use `set_input_map` to change the controls.
+    pub fn with_ball(width: f32, height: f32, ball: Ball) -> Simulation {
+        Simulation {
+            width,
+            height,
+            seed: None,
+
+            left_paddle: Paddle::with_binding(25.0, height / 2.0, Bindings::wasd()),
+            right_paddle: Paddle::with_binding(width - 25.0, height / 2.0, Bindings::arrows()),
+
+            ball,
+        }
+    }
+
+    /// Returns the parameters to advance this match by `dt` seconds.
+    pub fn update_params(&self, dt: f32) -> UpdateParams {
+        UpdateParams {
+            dt,
+            game_width: self.width,
+            game_height: self.height,
+        }
+    }
+
+    /// Gives each paddle the bindings for its player.
+    pub fn set_input_map(&mut self, input_map: &InputMap) {
+        self.left_paddle.binding = input_map.left.clone();
+        self.right_paddle.binding = input_map.right.clone();
+    }
+
+    /// Reads the input for both paddles from the current input state,
+    /// using each paddle's bindings.
+    pub fn read_input(&self, state: &InputState) -> Input {
+        Input {
+            left: self.left_paddle.binding.read(state),
+            right: self.right_paddle.binding.read(state),
+        }
+    }
+
+    /// Advances the match by one tick.
+    /// Nothing moves once a player has won.
+    pub fn step(&mut self, params: &UpdateParams, input: &Input) {
+        if self.winner().is_none() {
+            // The player receiving the serve can start it early once they're ready.
+            if input.paddle(self.ball.receiver()).serve {
+                self.ball.serve_now();
+            }
+            self.left_paddle.update(params, &input.left);
+            self.right_paddle.update(params, &input.right);
+            self.ball.update(params, &mut self.left_paddle, &mut self.right_paddle);
+        }
+    }
+
+    /// Predicts the height of the ball's center when it reaches the paddle on the given side,
+    /// simulating updates of `dt` seconds and bouncing off the walls on the way.
+    /// Returns `None` if the ball is heading the other way.
+    pub fn predict_intercept(&self, side: Side, dt: f32) -> Option<f32> {
+        let paddle = self.paddle(side).bounds;
+        let x = match side {
+            Side::Left => paddle.x + paddle.width,
+            Side::Right => paddle.x,
+        };
+        self.ball.predict_y_at(&self.update_params(dt), x)
+    }
+
+    /// The winner is the first player to reach `SCORE_TO_WIN` points.
+    pub fn winner(&self) -> Option<Side> {
+        if self.left_paddle.score >= SCORE_TO_WIN {
+            Some(Side::Left)
+        } else if self.right_paddle.score >= SCORE_TO_WIN {
+            Some(Side::Right)
+        } else {
+            None
+        }
+    }
+
+    /// The seed that the match was created with, or `None` if it was given its ball.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// A fingerprint of everything that affects how the match plays out from here.
+    /// Matches with the same hash are, as far as can be told, in exactly the same state.
+    pub fn state_hash(&self) -> u64 {
+        let mut bytes = Vec::new();
+        self.write_state(&mut bytes);
+        let mut hasher = StateHasher::new();
+        hasher.write(&bytes);
+        hasher.finish()
+    }
+
+    /// Writes everything about the match that affects how it plays out from here, bit for bit,
+    /// so that it can be restored with `read_state`. The seed, serve settings and bindings are
+    /// left out, since they don't change during a match.
+    pub fn write_state(&self, out: &mut Vec<u8>) {
+        state::write_f32(out, self.width);
+        state::write_f32(out, self.height);
+        self.left_paddle.write_state(out);
+        self.right_paddle.write_state(out);
+        self.ball.write_state(out);
+    }
+
+    /// Restores the state written by `write_state`, returning `None` if it is incomplete or
+    /// invalid.
+    pub fn read_state(&mut self, mut input: &[u8]) -> Option<()> {
+        let mut restored = self.clone();
+        restored.width = state::read_f32(&mut input)?;
+        restored.height = state::read_f32(&mut input)?;
+        restored.left_paddle.read_state(&mut input)?;
+        restored.right_paddle.read_state(&mut input)?;
+        restored.ball.read_state(&mut input)?;
+        if !input.is_empty() {
+            return None;
+        }
+        *self = restored;
+        Some(())
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn left_paddle(&self) -> &Paddle {
+        &self.left_paddle
+    }
+
+    pub fn right_paddle(&self) -> &Paddle {
+        &self.right_paddle
+    }
+
+    /// Returns the paddle defending the given side.
+    pub fn paddle(&self, side: Side) -> &Paddle {
+        match side {
+            Side::Left => &self.left_paddle,
+            Side::Right => &self.right_paddle,
+        }
+    }
+
+    /// Returns the paddle defending the given side, for changing its settings.
+    pub fn paddle_mut(&mut self, side: Side) -> &mut Paddle {
+        match side {
+            Side::Left => &mut self.left_paddle,
+            Side::Right => &mut self.right_paddle,
+        }
+    }
+
+    pub fn ball(&self) -> &Ball {
+        &self.ball
+    }
+}
+
+/// Hashes with 64-bit FNV-1a, which unlike the standard library's hasher is guaranteed to
+/// give the same results on every version of Rust.
+struct StateHasher(u64);
+
+impl StateHasher {
+    fn new() -> StateHasher {
+        StateHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StateHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Key, GAME_HEIGHT, GAME_WIDTH};
+
+    fn simulation() -> Simulation {
+        Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, 1)
+    }
+
+    /// Plays a match with input that exercises every way of moving, serving early now and
+    /// then, and returns the state hash after every tick.
+    fn play_scripted(seed: u64, ticks: u32) -> Vec<u64> {
+        let mut sim = Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, seed);
+        let params = sim.update_params(1.0 / 60.0);
+        (0..ticks)
+            .map(|tick| {
+                let phase = tick / 45 % 4;
+                let input = Input {
+                    left: PaddleInput {
+                        up: phase == 0,
+                        down: phase == 2,
+                        target_y: Some((tick % 700) as f32),
+                        serve: tick % 500 == 0,
+                        ..PaddleInput::default()
+                    },
+                    right: PaddleInput {
+                        axis: ((tick as f32) * 0.05).sin(),
+                        serve: tick % 300 == 0,
+                        ..PaddleInput::default()
+                    },
+                };
+                sim.step(&params, &input);
+                sim.state_hash()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn paddles_follow_input() {
+        let mut sim = simulation();
+        let params = sim.update_params(0.1);
+        let start = sim.left_paddle().bounds.y;
+        let input = Input {
+            left: PaddleInput { up: true, ..PaddleInput::default() },
+            right: PaddleInput { down: true, ..PaddleInput::default() },
+        };
+        sim.step(&params, &input);
+        assert!(sim.left_paddle().bounds.y < start);
+        assert!(sim.right_paddle().bounds.y > start);
+    }
+
+    #[test]
+    fn paddles_have_separate_keys() {
+        let mut sim = simulation();
+        let params = sim.update_params(0.1);
+        let start = sim.left_paddle().bounds.y;
+        let mut state = InputState::new();
+        state.keys.extend(&[Key::S, Key::Up]);
+        let input = sim.read_input(&state);
+        sim.step(&params, &input);
+        assert!(sim.left_paddle().bounds.y > start);
+        assert!(sim.right_paddle().bounds.y < start);
+    }
+
+    #[test]
+    fn receiver_can_serve_early() {
+        let mut sim = simulation();
+        let params = sim.update_params(0.1);
+        let receiver = sim.ball().receiver();
+        let mut input = Input::default();
+        input.paddle_mut(receiver.opposite()).serve = true;
+        sim.step(&params, &input);
+        assert!(sim.ball().start_timer() > 0.0);
+
+        input.paddle_mut(receiver).serve = true;
+        sim.step(&params, &input);
+        assert_eq!(sim.ball().start_timer(), 0.0);
+        assert!(sim.ball().bounds().x != sim.ball().previous_bounds().x);
+    }
+
+    #[test]
+    fn predicts_intercept_for_receiver_only() {
+        let sim = simulation();
+        let receiver = sim.ball().receiver();
+        let y = sim.predict_intercept(receiver, 1.0 / 60.0).unwrap();
+        assert!(y >= 0.0 && y <= sim.height());
+        assert_eq!(sim.predict_intercept(receiver.opposite(), 1.0 / 60.0), None);
+    }
+
+    #[test]
+    fn same_seed_and_input_stay_in_lockstep() {
+        let first = play_scripted(1234, 20_000);
+        let second = play_scripted(1234, 20_000);
+        assert!(first == second);
+
+        // The hash follows the match, so a different seed soon gives different hashes.
+        let other = play_scripted(1235, 20_000);
+        assert!(first.last() != other.last());
+        assert!(first.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn new_matches_remember_their_seed() {
+        let sim = Simulation::new(GAME_WIDTH as f32, GAME_HEIGHT as f32);
+        let seed = sim.seed().unwrap();
+        let again = Simulation::with_seed(sim.width(), sim.height(), seed);
+        assert_eq!(sim.state_hash(), again.state_hash());
+    }
+
+    #[test]
+    fn restored_state_plays_out_the_same() {
+        let mut sim = simulation();
+        let params = sim.update_params(1.0 / 60.0);
+        for _ in 0..500 {
+            sim.step(&params, &Input::default());
+        }
+        let mut bytes = Vec::new();
+        sim.write_state(&mut bytes);
+        let mut restored = simulation();
+        restored.read_state(&bytes).unwrap();
+        for _ in 0..500 {
+            sim.step(&params, &Input::default());
+            restored.step(&params, &Input::default());
+        }
+        assert_eq!(sim.state_hash(), restored.state_hash());
+
+        assert_eq!(restored.read_state(&bytes[..bytes.len() - 1]), None);
+    }
+
+    #[test]
+    fn unattended_match_finishes() {
+        let mut sim = simulation();
+        let params = sim.update_params(1.0 / 60.0);
+        for _ in 0..60 * 60 * 10 {
+            sim.step(&params, &Input::default());
+            if sim.winner().is_some() {
+                break;
+            }
+        }
+        let winner = sim.winner().expect("nobody won");
+        assert_eq!(sim.paddle(winner).score, SCORE_TO_WIN);
+        assert!(sim.paddle(winner.opposite()).score < SCORE_TO_WIN);
+
+        // The match is frozen once it's over.
+        let ball = sim.ball().bounds();
+        sim.step(&params, &Input::default());
+        assert_eq!(sim.ball().bounds().x, ball.x);
+    }
+}
@@ -0,0 +1,188 @@
+//! Draws the game into an in-memory RGBA framebuffer on the CPU.
+//!
+//! The output matches the glium renderer: the game is scaled to fit the framebuffer and
+//! centered with black bars, and a pixel is filled when its center lies inside a rectangle.
+
+use png;
+use render::{Color, Letterbox, Renderer};
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use Rectangle;
+
+/// A renderer that rasterizes rectangles into a framebuffer of 8-bit RGBA pixels.
+#[derive(Clone, Debug)]
+pub struct SoftwareRenderer {
+    width: u32,
+    height: u32,
+    game_width: f32,
+    game_height: f32,
+    conservative: bool,
+    pixels: Vec<u8>,
+}
+
+impl SoftwareRenderer {
+    /// Creates a `width` by `height` pixel framebuffer for a game of the given size.
+    /// The framebuffer starts out fully transparent.
+    pub fn new(width: u32, height: u32, game_width: f32, game_height: f32) -> SoftwareRenderer {
+        SoftwareRenderer {
+            width,
+            height,
+            game_width,
+            game_height,
+            conservative: false,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    /// When set, every pixel that a rectangle touches is filled, not just those whose centers
+    /// it covers. This keeps small objects such as the ball visible at very low resolutions.
+    pub fn set_conservative(&mut self, conservative: bool) {
+        self.conservative = conservative;
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The framebuffer in row-major order from the top left, four bytes per pixel.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Returns the color of a single pixel.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    /// Encodes the framebuffer as a PNG image.
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(to_io_error)?;
+        writer.write_image_data(&self.pixels).map_err(to_io_error)
+    }
+
+    /// Saves the framebuffer as a PNG file.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.write_png(BufWriter::new(file))
+    }
+
+    /// Converts a span in framebuffer coordinates to the range of pixels that should be filled.
+    fn pixel_range(&self, start: f32, end: f32, limit: u32) -> (u32, u32) {
+        let (first, last) = if self.conservative {
+            (start.floor(), end.ceil())
+        } else {
+            ((start - 0.5).ceil(), (end - 0.5).ceil())
+        };
+        (first.clamp(0.0, limit as f32) as u32, last.clamp(0.0, limit as f32) as u32)
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    type Error = Infallible;
+
+    fn clear(&mut self, color: Color) -> Result<(), Infallible> {
+        let color = to_rgba8(color);
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+        Ok(())
+    }
+
+    /// Draws a rectangle onto the framebuffer.
+    /// x and y are in game coordinates (1280x720) with (0, 0) at the top left of the frame.
+    fn draw_rectangle(&mut self, rect: Rectangle, color: Color) -> Result<(), Infallible> {
+        // Scale the game to fit inside the framebuffer and center it.
+        let letterbox = Letterbox::new(self.width as f32, self.height as f32, self.game_width, self.game_height);
+        let (left, top) = letterbox.to_frame(rect.x, rect.y);
+        let (right, bottom) = letterbox.to_frame(rect.x + rect.width, rect.y + rect.height);
+
+        let (x0, x1) = self.pixel_range(left, right, self.width);
+        let (y0, y1) = self.pixel_range(top, bottom, self.height);
+
+        let color = to_rgba8(color);
+        for y in y0..y1 {
+            let row = (y * self.width) as usize;
+            for x in x0..x1 {
+                let i = (row + x as usize) * 4;
+                self.pixels[i..i + 4].copy_from_slice(&color);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts a floating point color to 8 bits per channel.
+fn to_rgba8(color: Color) -> [u8; 4] {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(color[0]), channel(color[1]), channel(color[2]), channel(color[3])]
+}
+
+fn to_io_error(err: png::EncodingError) -> io::Error {
+    io::Error::other(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_pixels_with_centers_inside() {
+        let mut renderer = SoftwareRenderer::new(8, 8, 8.0, 8.0);
+        renderer.draw_rectangle(Rectangle::new(1.0, 2.0, 2.0, 1.6), [1.0, 0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(renderer.pixel(0, 2), [0, 0, 0, 0]);
+        assert_eq!(renderer.pixel(1, 2), [255, 0, 0, 255]);
+        assert_eq!(renderer.pixel(2, 3), [255, 0, 0, 255]);
+        assert_eq!(renderer.pixel(3, 2), [0, 0, 0, 0]);
+        assert_eq!(renderer.pixel(1, 4), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn conservative_fills_touched_pixels() {
+        let mut renderer = SoftwareRenderer::new(8, 8, 8.0, 8.0);
+        renderer.set_conservative(true);
+        renderer.draw_rectangle(Rectangle::new(1.2, 2.6, 0.3, 0.3), [1.0, 0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(renderer.pixel(1, 2), [255, 0, 0, 255]);
+        assert_eq!(renderer.pixels().iter().filter(|&&b| b == 255).count(), 2);
+    }
+
+    #[test]
+    fn clips_to_framebuffer() {
+        let mut renderer = SoftwareRenderer::new(4, 4, 4.0, 4.0);
+        renderer.draw_rectangle(Rectangle::new(-10.0, 2.0, 100.0, 100.0), [1.0, 1.0, 1.0, 1.0]).unwrap();
+        assert_eq!(renderer.pixel(0, 1), [0, 0, 0, 0]);
+        assert_eq!(renderer.pixel(0, 2), [255; 4]);
+        assert_eq!(renderer.pixel(3, 3), [255; 4]);
+    }
+
+    #[test]
+    fn letterboxes_wide_game() {
+        // A 2:1 game in a square framebuffer leaves bars above and below.
+        let mut renderer = SoftwareRenderer::new(100, 100, 200.0, 100.0);
+        renderer.clear([0.0, 0.0, 1.0, 1.0]).unwrap();
+        renderer.draw_rectangle(Rectangle::new(0.0, 0.0, 200.0, 100.0), [1.0, 1.0, 1.0, 1.0]).unwrap();
+        assert_eq!(renderer.pixel(50, 24), [0, 0, 255, 255]);
+        assert_eq!(renderer.pixel(50, 25), [255; 4]);
+        assert_eq!(renderer.pixel(0, 74), [255; 4]);
+        assert_eq!(renderer.pixel(99, 75), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn pillarboxes_tall_game() {
+        let mut renderer = SoftwareRenderer::new(100, 100, 50.0, 100.0);
+        renderer.draw_rectangle(Rectangle::new(0.0, 0.0, 50.0, 100.0), [1.0, 1.0, 1.0, 1.0]).unwrap();
+        assert_eq!(renderer.pixel(24, 50), [0, 0, 0, 0]);
+        assert_eq!(renderer.pixel(25, 0), [255; 4]);
+        assert_eq!(renderer.pixel(74, 99), [255; 4]);
+        assert_eq!(renderer.pixel(75, 50), [0, 0, 0, 0]);
+    }
+}
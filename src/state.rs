@@ -0,0 +1,111 @@
+//! Writes and reads the complete state of a match as bytes, for replay snapshots, state hashes
+//! and network packets. Numbers are little-endian, and floats are kept bit for bit.
+//!
+//! Readers take bytes from the front of a slice, and return `None` if it runs out.
+//!
+//! A delta between two states of the same length has a bit for each 4-byte chunk of the state,
+//! set if the chunk changed, followed by the chunks that changed. Most of a match stays the same
+//! from one tick to the next, so deltas are usually much smaller than the state.
+
+pub fn write_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+pub fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_f32(out: &mut Vec<u8>, value: f32) {
+    write_u32(out, value.to_bits());
+}
+
+const DELTA_CHUNK_SIZE: usize = 4;
+
+/// Writes the differences from `base` to `state`, which must be the same length.
+pub fn write_delta(out: &mut Vec<u8>, base: &[u8], state: &[u8]) {
+    debug_assert_eq!(base.len(), state.len());
+    let mask_start = out.len();
+    out.resize(mask_start + mask_size(base.len()), 0);
+    for (i, (old, new)) in base.chunks(DELTA_CHUNK_SIZE).zip(state.chunks(DELTA_CHUNK_SIZE)).enumerate() {
+        if old != new {
+            out[mask_start + i / 8] |= 1 << (i % 8);
+            out.extend_from_slice(new);
+        }
+    }
+}
+
+/// Reads a delta written by `write_delta`, returning the state it leads to from `base`.
+pub fn read_delta(input: &mut &[u8], base: &[u8]) -> Option<Vec<u8>> {
+    let mask = read_bytes(input, mask_size(base.len()))?;
+    let mut state = base.to_vec();
+    for (i, chunk) in state.chunks_mut(DELTA_CHUNK_SIZE).enumerate() {
+        if mask[i / 8] & (1 << (i % 8)) != 0 {
+            let len = chunk.len();
+            chunk.copy_from_slice(read_bytes(input, len)?);
+        }
+    }
+    Some(state)
+}
+
+// The number of bytes in a delta's mask for a state of the given length.
+fn mask_size(len: usize) -> usize {
+    len.div_ceil(DELTA_CHUNK_SIZE).div_ceil(8)
+}
+
+fn read_bytes<'a>(input: &mut &'a [u8], count: usize) -> Option<&'a [u8]> {
+    if input.len() < count {
+        return None;
+    }
+    let (bytes, rest) = input.split_at(count);
+    *input = rest;
+    Some(bytes)
+}
+
+fn read_array<const N: usize>(input: &mut &[u8]) -> Option<[u8; N]> {
+    let mut array = [0; N];
+    array.copy_from_slice(read_bytes(input, N)?);
+    Some(array)
+}
+
+pub fn read_u8(input: &mut &[u8]) -> Option<u8> {
+    read_array::<1>(input).map(|[byte]| byte)
+}
+
+pub fn read_u32(input: &mut &[u8]) -> Option<u32> {
+    read_array(input).map(u32::from_le_bytes)
+}
+
+pub fn read_u64(input: &mut &[u8]) -> Option<u64> {
+    read_array(input).map(u64::from_le_bytes)
+}
+
+pub fn read_f32(input: &mut &[u8]) -> Option<f32> {
+    read_u32(input).map(f32::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas_carry_only_what_changed() {
+        let base: Vec<u8> = (0..50).collect();
+        let mut state = base.clone();
+        state[5] = 200;
+        state[49] = 201;
+
+        let mut delta = Vec::new();
+        write_delta(&mut delta, &base, &state);
+        // A two byte mask, and the two chunks that changed, the last of which is short.
+        assert_eq!(delta.len(), 2 + 4 + 2);
+        let mut input = &delta[..];
+        assert_eq!(read_delta(&mut input, &base), Some(state));
+        assert!(input.is_empty());
+
+        assert_eq!(read_delta(&mut &delta[..delta.len() - 1], &base), None);
+    }
+}
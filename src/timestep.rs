@@ -0,0 +1,84 @@
+//! Runs the simulation at a fixed rate, independent of how often frames are drawn.
+//!
+//! Real time is collected into an accumulator and spent in fixed-size ticks.
+//! Whatever is left over is used to blend between the last two ticks when rendering.
+//! For more info, see "Fix Your Timestep!":
+//! https://gafferongames.com/post/fix_your_timestep/
+
+use std::time::Duration;
+
+/// Tracks how many fixed-size ticks are due.
+#[derive(Clone, Debug)]
+pub struct FixedTimestep {
+    step: f32,
+    max_ticks_per_frame: u32,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    /// Creates a timestep that runs `tick_rate` ticks per second.
+    /// At most `max_ticks_per_frame` ticks are run to catch up after a slow frame;
+    /// any time beyond that is dropped, slowing the game down instead of freezing it.
+    pub fn new(tick_rate: f32, max_ticks_per_frame: u32) -> FixedTimestep {
+        FixedTimestep {
+            step: 1.0 / tick_rate,
+            max_ticks_per_frame,
+            accumulator: 0.0,
+        }
+    }
+
+    /// The length of a single tick in seconds.
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Adds the real time that has elapsed and returns the number of ticks to run.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        let elapsed = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1.0e-9;
+        self.accumulator += elapsed;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.step && ticks < self.max_ticks_per_frame {
+            self.accumulator -= self.step;
+            ticks += 1;
+        }
+
+        // Too far behind to catch up; forget the time we couldn't simulate.
+        if self.accumulator >= self.step {
+            self.accumulator = 0.0;
+        }
+        ticks
+    }
+
+    /// How far between the previous tick and the next one the current frame is, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn ticks_at_fixed_rate() {
+        let mut timestep = FixedTimestep::new(100.0, 5);
+        assert_eq!(timestep.advance(millis(5)), 0);
+        assert!((timestep.alpha() - 0.5).abs() < 0.001);
+        assert_eq!(timestep.advance(millis(5)), 1);
+        assert_eq!(timestep.advance(millis(25)), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn limits_catch_up() {
+        let mut timestep = FixedTimestep::new(100.0, 5);
+        assert_eq!(timestep.advance(millis(1000)), 5);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(millis(10)), 1);
+    }
+}
@@ -0,0 +1,335 @@
+//! Plays batches of matches between controllers without a window, as fast as possible,
+//! and sums up how they did.
+//!
+//! Every match is seeded from the tournament's seed, so matches between the AIs come out the
+//! same every time. Rates and averages come with 95% confidence intervals, so that a change to
+//! a bot can be told apart from luck.
+
+use bot::BotError;
+use rand::Rng;
+use serde::Serializer;
+use serde_json;
+use std::fmt::Write;
+use std::time::Duration;
+use {Controller, ControllerKind, GameRng, Input, Side, Simulation};
+use {GAME_HEIGHT, GAME_WIDTH};
+
+// The z-score for a 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+// Nothing is shown while matches are played, so bots can take longer to answer than in a game.
+const BOT_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Settings for a tournament.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TournamentConfig {
+    pub left: ControllerKind,
+    pub right: ControllerKind,
+    /// The number of matches to play.
+    pub matches: u32,
+    pub seed: u64,
+    /// Ticks per second of game time.
+    pub tick_rate: f32,
+    /// Stops matches that go on for this many ticks without a winner.
+    pub max_ticks: u64,
+}
+
+impl TournamentConfig {
+    /// Creates settings for 100 matches at 60 ticks per second, stopping matches after an
+    /// hour of game time.
+    pub fn new(left: ControllerKind, right: ControllerKind) -> TournamentConfig {
+        TournamentConfig {
+            left,
+            right,
+            matches: 100,
+            seed: 0,
+            tick_rate: 60.0,
+            max_ticks: 60 * 60 * 60,
+        }
+    }
+}
+
+/// How one match went.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MatchResult {
+    pub seed: u64,
+    /// The winner, or `None` if the match was stopped after `max_ticks`.
+    #[serde(serialize_with = "serialize_side")]
+    pub winner: Option<Side>,
+    pub left_score: u32,
+    pub right_score: u32,
+    pub ticks: u64,
+    /// The number of times the ball was returned during each point, in order, ending with the
+    /// point that was being played if the match was stopped.
+    pub rallies: Vec<u32>,
+    /// The fastest the ball went, in pixels per second.
+    pub max_ball_speed: f32,
+}
+
+fn serialize_side<S: Serializer>(side: &Option<Side>, serializer: S) -> Result<S::Ok, S::Error> {
+    match *side {
+        Some(side) => serializer.serialize_str(side.name()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// A value measured over a tournament, with its 95% confidence interval.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Estimate {
+    pub value: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Estimate {
+    /// Estimates a rate from the number of successes out of `count` tries, using the Wilson
+    /// score interval, which stays sensible for rates near 0 or 1.
+    pub fn rate(successes: u32, count: u32) -> Estimate {
+        if count == 0 {
+            return Estimate { value: 0.0, low: 0.0, high: 1.0 };
+        }
+        let n = f64::from(count);
+        let p = f64::from(successes) / n;
+        let z2 = Z_95 * Z_95;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin = Z_95 / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        Estimate { value: p, low: (center - margin).max(0.0), high: (center + margin).min(1.0) }
+    }
+
+    /// Estimates the mean of the population that the samples came from.
+    pub fn mean(samples: &[f64]) -> Estimate {
+        let n = samples.len() as f64;
+        let mean = if samples.is_empty() { 0.0 } else { samples.iter().sum::<f64>() / n };
+        if samples.len() < 2 {
+            return Estimate { value: mean, low: mean, high: mean };
+        }
+        let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+        let margin = Z_95 * (variance / n).sqrt();
+        Estimate { value: mean, low: mean - margin, high: mean + margin }
+    }
+}
+
+/// The results of a tournament.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub left: String,
+    pub right: String,
+    pub seed: u64,
+    pub matches: u32,
+    pub left_wins: u32,
+    pub right_wins: u32,
+    /// Matches stopped without a winner.
+    pub unfinished: u32,
+    pub left_win_rate: Estimate,
+    pub right_win_rate: Estimate,
+    /// The number of times the ball was returned per point.
+    pub rally_length: Estimate,
+    /// The fastest the ball went in any match, in pixels per second.
+    pub max_ball_speed: f32,
+    /// The fastest the ball went in each match, on average.
+    pub match_max_ball_speed: Estimate,
+    pub results: Vec<MatchResult>,
+}
+
+impl Report {
+    /// Sums up the results of the matches.
+    pub fn new(config: &TournamentConfig, results: Vec<MatchResult>) -> Report {
+        let count = results.len() as u32;
+        let wins = |side| results.iter().filter(|result| result.winner == Some(side)).count() as u32;
+        let (left_wins, right_wins) = (wins(Side::Left), wins(Side::Right));
+        let rallies: Vec<f64> = results.iter().flat_map(|result| &result.rallies).map(|&hits| f64::from(hits)).collect();
+        let speeds: Vec<f64> = results.iter().map(|result| f64::from(result.max_ball_speed)).collect();
+        Report {
+            left: config.left.to_string(),
+            right: config.right.to_string(),
+            seed: config.seed,
+            matches: count,
+            left_wins,
+            right_wins,
+            unfinished: count - left_wins - right_wins,
+            left_win_rate: Estimate::rate(left_wins, count),
+            right_win_rate: Estimate::rate(right_wins, count),
+            rally_length: Estimate::mean(&rallies),
+            max_ball_speed: results.iter().map(|result| result.max_ball_speed).fold(0.0, f32::max),
+            match_max_ball_speed: Estimate::mean(&speeds),
+            results,
+        }
+    }
+
+    /// Writes the whole report as JSON, including the results of each match.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+
+    /// Writes the summary as CSV, one statistic per row with its confidence interval.
+    /// Counts have no interval, so their bounds are the count itself.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("statistic,value,low,high\n");
+        let mut row = |name: &str, estimate: Estimate| {
+            writeln!(csv, "{},{},{},{}", name, estimate.value, estimate.low, estimate.high).unwrap();
+        };
+        let count = |value: u32| Estimate { value: f64::from(value), low: f64::from(value), high: f64::from(value) };
+        row("matches", count(self.matches));
+        row("left_wins", count(self.left_wins));
+        row("right_wins", count(self.right_wins));
+        row("unfinished", count(self.unfinished));
+        row("left_win_rate", self.left_win_rate);
+        row("right_win_rate", self.right_win_rate);
+        row("rally_length", self.rally_length);
+        let speed = f64::from(self.max_ball_speed);
+        row("max_ball_speed", Estimate { value: speed, low: speed, high: speed });
+        row("match_max_ball_speed", self.match_max_ball_speed);
+        csv
+    }
+}
+
+/// Plays all of the tournament's matches, one after another.
+pub fn run(config: &TournamentConfig) -> Result<Report, BotError> {
+    let mut rng = GameRng::from_seed(config.seed);
+    let results = (0..config.matches)
+        .map(|_| play_match(config, rng.next_u64()))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Report::new(config, results))
+}
+
+/// Plays a match to `SCORE_TO_WIN`, or until `max_ticks` is reached.
+pub fn play_match(config: &TournamentConfig, seed: u64) -> Result<MatchResult, BotError> {
+    let mut rng = GameRng::from_seed(seed);
+    let mut simulation = Simulation::with_seed(GAME_WIDTH as f32, GAME_HEIGHT as f32, rng.next_u64());
+    let mut left = config.left.create(Side::Left, GameRng::from_seed(rng.next_u64()))?;
+    let mut right = config.right.create(Side::Right, GameRng::from_seed(rng.next_u64()))?;
+    for controller in &mut [&mut left, &mut right] {
+        if let Controller::Bot(ref mut bot) = **controller {
+            bot.set_timeout(BOT_TIMEOUT);
+        }
+    }
+    let params = simulation.update_params(1.0 / config.tick_rate);
+
+    let mut ticks = 0;
+    let mut rallies = Vec::new();
+    let mut returns = 0;
+    let mut point_ended = false;
+    let mut max_ball_speed: f32 = 0.0;
+    while simulation.winner().is_none() && ticks < config.max_ticks {
+        let input = Input {
+            left: left.update(&simulation, params.dt)?,
+            right: right.update(&simulation, params.dt)?,
+        };
+        let points = simulation.left_paddle().score + simulation.right_paddle().score;
+        let vx = simulation.ball().velocity().0;
+        simulation.step(&params, &input);
+        ticks += 1;
+
+        // A point ends the rally, while the ball turning around without one means it was returned.
+        point_ended = simulation.left_paddle().score + simulation.right_paddle().score != points;
+        if point_ended {
+            rallies.push(returns);
+            returns = 0;
+        } else if simulation.ball().velocity().0.signum() != vx.signum() {
+            returns += 1;
+        }
+        let (vx, vy) = simulation.ball().velocity();
+        max_ball_speed = max_ball_speed.max(vx.hypot(vy));
+    }
+
+    // Count the rally that was cut short if the match was stopped partway through a point.
+    if ticks > 0 && !point_ended {
+        rallies.push(returns);
+    }
+
+    // Let bots know how the match ended.
+    for controller in &mut [left, right] {
+        if let Controller::Bot(ref mut bot) = *controller {
+            bot.finish(simulation.winner())?;
+        }
+    }
+
+    Ok(MatchResult {
+        seed,
+        winner: simulation.winner(),
+        left_score: simulation.left_paddle().score,
+        right_score: simulation.right_paddle().score,
+        ticks,
+        rallies,
+        max_ball_speed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Difficulty, SCORE_TO_WIN};
+
+    fn config(left: Difficulty, right: Difficulty, matches: u32) -> TournamentConfig {
+        TournamentConfig { matches, ..TournamentConfig::new(ControllerKind::Ai(left), ControllerKind::Ai(right)) }
+    }
+
+    #[test]
+    fn plays_matches_to_the_end() {
+        let report = run(&config(Difficulty::Hard, Difficulty::Easy, 3)).unwrap();
+        assert_eq!(report.results.len(), 3);
+        assert_eq!(report.left_wins + report.right_wins + report.unfinished, 3);
+        for result in &report.results {
+            let winner = result.winner.expect("the match wasn't finished");
+            let score = if winner == Side::Left { result.left_score } else { result.right_score };
+            assert_eq!(score, SCORE_TO_WIN);
+            assert_eq!(result.rallies.len() as u32, result.left_score + result.right_score);
+        }
+        assert!(report.left_wins > report.right_wins);
+        assert!(report.rally_length.value > 0.0);
+        assert!(report.max_ball_speed > 0.0);
+    }
+
+    #[test]
+    fn same_seed_gives_same_report() {
+        let config = config(Difficulty::Medium, Difficulty::Medium, 2);
+        assert_eq!(run(&config).unwrap(), run(&config).unwrap());
+    }
+
+    #[test]
+    fn unfinished_matches_have_no_winner() {
+        let config = TournamentConfig { max_ticks: 10, ..config(Difficulty::Easy, Difficulty::Easy, 1) };
+        let report = run(&config).unwrap();
+        assert_eq!(report.unfinished, 1);
+        let result = &report.results[0];
+        assert_eq!(result.winner, None);
+        assert_eq!(result.rallies.len() as u32, result.left_score + result.right_score + 1);
+    }
+
+    #[test]
+    fn rate_intervals_contain_the_rate() {
+        let estimate = Estimate::rate(7, 10);
+        assert_eq!(estimate.value, 0.7);
+        assert!((estimate.low - 0.3968).abs() < 1e-3, "{:?}", estimate);
+        assert!((estimate.high - 0.8922).abs() < 1e-3, "{:?}", estimate);
+
+        let estimate = Estimate::rate(0, 10);
+        assert_eq!(estimate.low, 0.0);
+        assert!(estimate.high > 0.0);
+    }
+
+    #[test]
+    fn mean_interval_narrows_with_more_samples() {
+        let few = Estimate::mean(&[1.0, 3.0]);
+        let many = Estimate::mean(&[1.0, 3.0, 1.0, 3.0, 1.0, 3.0, 1.0, 3.0]);
+        assert_eq!((few.value, many.value), (2.0, 2.0));
+        assert!(many.high - many.low < few.high - few.low);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_statistic() {
+        let report = Report::new(&config(Difficulty::Easy, Difficulty::Hard, 0), Vec::new());
+        let csv = report.to_csv();
+        assert!(csv.starts_with("statistic,value,low,high\n"));
+        assert!(csv.contains("\nleft_win_rate,0,0,1\n"));
+        assert_eq!(csv.lines().count(), 10);
+    }
+
+    #[test]
+    fn json_names_the_winner() {
+        let report = run(&config(Difficulty::Hard, Difficulty::Easy, 1)).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["left"], "hard");
+        assert_eq!(json["results"][0]["winner"], "left");
+    }
+}
@@ -0,0 +1,399 @@
+//! Plays the game inside a terminal, so that it works over SSH.
+//!
+//! The game is rasterized with the software renderer at two pixels per character cell and
+//! drawn using the upper half block character, with the top pixel as the foreground color and
+//! the bottom pixel as the background color. Keys are read from stdin in raw mode.
+//!
+//! Terminals only report key presses, not releases, so a key counts as held for a short time
+//! after each press. Holding a key down relies on the terminal's key repeat.
+//! The mouse isn't supported, so only the keys in the controls file have any effect.
+
+use libc;
+use rusty_pong::render::{self, Color, Renderer};
+use rusty_pong::software_renderer::SoftwareRenderer;
+use rusty_pong::{Action, Controller, FixedTimestep, InputMap, InputState, Key, Rectangle, Simulation};
+use rusty_pong::Trigger;
+use rusty_pong::{GAME_HEIGHT, GAME_WIDTH};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant};
+use {Result, GAME_MAX_TICKS_PER_FRAME, GAME_TICK_RATE};
+
+const TUI_FRAMERATE: f32 = 30.0;
+
+// How long a key stays held after the terminal reports it.
+// The first press waits out the terminal's key repeat delay.
+const KEY_FIRST_HOLD_TIME: Duration = Duration::from_millis(300);
+const KEY_REPEAT_HOLD_TIME: Duration = Duration::from_millis(100);
+
+// Ctrl-C always quits, since raw mode stops it from interrupting the game.
+const CTRL_C: u8 = 0x03;
+
+/// Runs the game in the terminal with the given controls, against the opponent if there is one,
+/// until the player quits.
+pub fn run(input_map: InputMap, mut opponent: Option<Controller>) -> Result<()> {
+    let _terminal = RawTerminal::enter()?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut stdin = io::stdin();
+
+    let (width, height) = (GAME_WIDTH as f32, GAME_HEIGHT as f32);
+    let mut simulation = Simulation::new(width, height);
+    simulation.set_input_map(&input_map);
+    let mut keys = HeldKeys::new();
+    let mut paused = false;
+    let mut timestep = FixedTimestep::new(GAME_TICK_RATE, GAME_MAX_TICKS_PER_FRAME);
+    let min_frame_time = Duration::from_millis((1000.0 / TUI_FRAMERATE) as u64);
+    let mut last_frame = Instant::now();
+    let mut parser = KeyParser::new();
+    let mut buffer = [0; 64];
+    loop {
+        // Read any keys that were pressed since the last frame.
+        let now = Instant::now();
+        let count = stdin.read(&mut buffer)?;
+        if buffer[..count].contains(&CTRL_C) {
+            return Ok(());
+        }
+        for key in parser.parse(&buffer[..count]) {
+            match input_map.game.action_for(Trigger::Key(key)) {
+                Some(Action::Quit) => return Ok(()),
+                Some(Action::Pause) => paused = !paused,
+                _ => keys.press(key, now, &opposite_keys(&input_map, key)),
+            }
+        }
+
+        // Update the game state.
+        let ticks = timestep.advance(now - last_frame);
+        last_frame = now;
+        let params = simulation.update_params(timestep.step());
+        let state = InputState { keys: keys.pressed(now), ..InputState::default() };
+        let input = simulation.read_input(&state);
+        if !paused {
+            for _ in 0..ticks {
+                let mut input = input;
+                if let Some(ref mut opponent) = opponent {
+                    *input.paddle_mut(opponent.side()) = opponent.update(&simulation, params.dt)?;
+                }
+                simulation.step(&params, &input);
+            }
+        }
+
+        // Draw the frame to fit the current size of the terminal.
+        let (columns, rows) = terminal_size()?;
+        let mut renderer = TuiRenderer::new(columns, rows, width, height);
+        render::draw_game(&mut renderer, &simulation, timestep.alpha()).unwrap_or_else(|e| match e {});
+        stdout.write_all(renderer.to_ansi().as_bytes())?;
+        stdout.flush()?;
+
+        let frame_time = last_frame.elapsed();
+        if frame_time < min_frame_time {
+            thread::sleep(min_frame_time - frame_time);
+        }
+    }
+}
+
+/// Draws the game into a grid of terminal cells.
+pub struct TuiRenderer {
+    pixels: SoftwareRenderer,
+}
+
+impl TuiRenderer {
+    /// Creates a renderer for a terminal with the given number of columns and rows.
+    pub fn new(columns: u32, rows: u32, game_width: f32, game_height: f32) -> TuiRenderer {
+        let mut pixels = SoftwareRenderer::new(columns, rows * 2, game_width, game_height);
+        pixels.set_conservative(true);
+        TuiRenderer { pixels }
+    }
+
+    /// Converts the drawing into text and ANSI escape codes that redraw the whole terminal.
+    pub fn to_ansi(&self) -> String {
+        let (columns, rows) = (self.pixels.width(), self.pixels.height() / 2);
+        let mut out = String::from("\x1b[H");
+        let mut colors = None;
+        for row in 0..rows {
+            if row > 0 {
+                out.push_str("\r\n");
+            }
+            for column in 0..columns {
+                let top = self.pixels.pixel(column, row * 2);
+                let bottom = self.pixels.pixel(column, row * 2 + 1);
+                if colors != Some((top, bottom)) {
+                    write!(out, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                        top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]).unwrap();
+                    colors = Some((top, bottom));
+                }
+                out.push('\u{2580}');
+            }
+        }
+        out.push_str("\x1b[0m");
+        out
+    }
+}
+
+impl Renderer for TuiRenderer {
+    type Error = Infallible;
+
+    fn clear(&mut self, color: Color) -> ::std::result::Result<(), Infallible> {
+        self.pixels.clear(color)
+    }
+
+    fn draw_rectangle(&mut self, rect: Rectangle, color: Color) -> ::std::result::Result<(), Infallible> {
+        self.pixels.draw_rectangle(rect, color)
+    }
+}
+
+/// Tracks which keys should count as held, given only key press events.
+struct HeldKeys {
+    release_times: HashMap<Key, Instant>,
+}
+
+impl HeldKeys {
+    fn new() -> HeldKeys {
+        HeldKeys { release_times: HashMap::new() }
+    }
+
+    /// Presses a key, letting go of any keys in `opposite` straight away.
+    fn press(&mut self, key: Key, now: Instant, opposite: &[Key]) {
+        let held = self.release_times.get(&key).is_some_and(|&release| release > now);
+        let hold_time = if held { KEY_REPEAT_HOLD_TIME } else { KEY_FIRST_HOLD_TIME };
+        self.release_times.insert(key, now + hold_time);
+        for key in opposite {
+            self.release_times.remove(key);
+        }
+    }
+
+    fn pressed(&self, now: Instant) -> HashSet<Key> {
+        self.release_times
+            .iter()
+            .filter(|&(_, &release)| release > now)
+            .map(|(&key, _)| key)
+            .collect()
+    }
+}
+
+/// Returns the keys that move the same paddle in the opposite direction to `key`.
+fn opposite_keys(input_map: &InputMap, key: Key) -> Vec<Key> {
+    let mut keys = Vec::new();
+    for bindings in &[&input_map.left, &input_map.right] {
+        let opposite = match bindings.action_for(Trigger::Key(key)) {
+            Some(Action::MoveUp) => Action::MoveDown,
+            Some(Action::MoveDown) => Action::MoveUp,
+            _ => continue,
+        };
+        for trigger in bindings.triggers_for(opposite) {
+            if let Trigger::Key(key) = trigger {
+                keys.push(key);
+            }
+        }
+    }
+    keys
+}
+
+/// Converts bytes read from a raw mode terminal into key presses.
+///
+/// An escape sequence can be split across reads, so any incomplete one is kept until the rest of
+/// it arrives. The Escape key sends a lone escape byte, which is only taken as Escape once a read
+/// brings nothing after it.
+struct KeyParser {
+    pending: Vec<u8>,
+}
+
+impl KeyParser {
+    fn new() -> KeyParser {
+        KeyParser { pending: Vec::new() }
+    }
+
+    /// Returns the keys pressed in the bytes from one read, and any completed by them.
+    fn parse(&mut self, bytes: &[u8]) -> Vec<Key> {
+        self.pending.extend_from_slice(bytes);
+        let mut keys = Vec::new();
+        let mut i = 0;
+        while i < self.pending.len() {
+            match parse_key(&self.pending[i..]) {
+                Some((key, length)) => {
+                    keys.extend(key);
+                    i += length;
+                }
+                None => {
+                    // Nothing more arrived, so the sequence isn't going to be finished.
+                    if bytes.is_empty() {
+                        if self.pending[i..] == [0x1b] {
+                            keys.push(Key::Escape);
+                        }
+                        i = self.pending.len();
+                    }
+                    break;
+                }
+            }
+        }
+        self.pending.drain(..i);
+        keys
+    }
+}
+
+/// Reads the key at the start of the bytes. Returns the key, if it is one the game knows, and the
+/// number of bytes it took up, or `None` if the bytes end partway through an escape sequence.
+fn parse_key(bytes: &[u8]) -> Option<(Option<Key>, usize)> {
+    let key = match *bytes {
+        [0x1b] | [0x1b, b'['] | [0x1b, b'O'] => return None,
+        // Arrow keys are sent as escape sequences in either normal or application mode.
+        [0x1b, b'O', code, ..] => return Some((arrow_key(code), 3)),
+        // Control sequences can have parameters, such as for modifier keys held with an arrow
+        // key, and end with a byte from @ to ~. Unknown ones are skipped.
+        [0x1b, b'[', ..] => {
+            let end = 2 + bytes[2..].iter().position(|byte| !(0x20..=0x3f).contains(byte))?;
+            return match bytes[end] {
+                0x40..=0x7e => Some((arrow_key(bytes[end]), end + 1)),
+                _ => Some((None, end)),
+            };
+        }
+        [0x1b, ..] => Some(Key::Escape),
+        [b' ', ..] => Some(Key::Space),
+        [b'\r', ..] => Some(Key::Return),
+        [b'\t', ..] => Some(Key::Tab),
+        [0x7f, ..] => Some(Key::Back),
+        [c @ b'0'..=b'9', ..] => Key::from_name(&format!("Key{}", c as char)),
+        [c, ..] if c.is_ascii_alphabetic() => Key::from_name(&(c as char).to_string()),
+        _ => None,
+    };
+    Some((key, 1))
+}
+
+/// Returns the arrow key for the final byte of an escape sequence.
+fn arrow_key(code: u8) -> Option<Key> {
+    match code {
+        b'A' => Some(Key::Up),
+        b'B' => Some(Key::Down),
+        b'C' => Some(Key::Right),
+        b'D' => Some(Key::Left),
+        _ => None,
+    }
+}
+
+/// Puts the terminal into raw mode on an alternate screen, and restores it when dropped.
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<RawTerminal> {
+        unsafe {
+            let mut original: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // Raw mode, with reads returning immediately even if no keys were pressed.
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // Switch to the alternate screen and hide the cursor.
+            print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+            io::stdout().flush()?;
+            Ok(RawTerminal { original })
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().unwrap_or(());
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Returns the size of the terminal in columns and rows.
+fn terminal_size() -> io::Result<(u32, u32)> {
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((u32::from(size.ws_col).max(1), u32::from(size.ws_row).max(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+        let mut parser = KeyParser::new();
+        let mut keys = parser.parse(bytes);
+        keys.extend(parser.parse(&[]));
+        keys
+    }
+
+    #[test]
+    fn parses_arrow_keys() {
+        let keys = parse_keys(b"\x1b[A\x1b[Bw\x1bOA\x1b[1;5C");
+        assert_eq!(keys, vec![Key::Up, Key::Down, Key::W, Key::Up, Key::Right]);
+    }
+
+    #[test]
+    fn parses_lone_escape() {
+        assert_eq!(parse_keys(b"\x1b"), vec![Key::Escape]);
+        assert_eq!(parse_keys(b"\x1bq"), vec![Key::Escape, Key::Q]);
+        assert_eq!(parse_keys(b"q"), vec![Key::Q]);
+    }
+
+    #[test]
+    fn keeps_escape_sequences_split_across_reads() {
+        let mut parser = KeyParser::new();
+        assert_eq!(parser.parse(b"w\x1b"), vec![Key::W]);
+        assert_eq!(parser.parse(b"[1;"), vec![]);
+        assert_eq!(parser.parse(b"5A"), vec![Key::Up]);
+        assert_eq!(parser.parse(&[]), vec![]);
+    }
+
+    #[test]
+    fn skips_unknown_escape_sequences() {
+        assert_eq!(parse_keys(b"\x1b[3~\x1b[15;2~s"), vec![Key::S]);
+        assert_eq!(parse_keys(b"\x1b[1;"), vec![]);
+    }
+
+    #[test]
+    fn opposite_keys_come_from_bindings() {
+        let input_map = InputMap::default();
+        assert_eq!(opposite_keys(&input_map, Key::W), vec![Key::S]);
+        assert_eq!(opposite_keys(&input_map, Key::Down), vec![Key::Up]);
+        assert!(opposite_keys(&input_map, Key::Space).is_empty());
+    }
+
+    #[test]
+    fn keys_are_held_until_released() {
+        let start = Instant::now();
+        let mut keys = HeldKeys::new();
+        keys.press(Key::Up, start, &[Key::Down]);
+        assert!(keys.pressed(start + Duration::from_millis(250)).contains(&Key::Up));
+        assert!(keys.pressed(start + KEY_FIRST_HOLD_TIME).is_empty());
+
+        // The opposite direction releases the first key.
+        keys.press(Key::Down, start, &[Key::Up]);
+        assert_eq!(keys.pressed(start), [Key::Down].iter().cloned().collect());
+    }
+
+    #[test]
+    fn draws_half_blocks() {
+        // A 4x2 terminal is 4x4 pixels; fill the top-left pixel of the bottom row of cells.
+        let mut renderer = TuiRenderer::new(4, 2, 4.0, 4.0);
+        renderer.clear([0.0, 0.0, 0.0, 1.0]).unwrap();
+        renderer.draw_rectangle(Rectangle::new(0.0, 2.0, 1.0, 1.0), [1.0, 1.0, 1.0, 1.0]).unwrap();
+        let black = "\x1b[38;2;0;0;0m\x1b[48;2;0;0;0m";
+        let white_top = "\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m";
+        let expected = format!("\x1b[H{}▀▀▀▀\r\n{}▀{}▀▀▀\x1b[0m", black, white_top, black);
+        assert_eq!(renderer.to_ansi(), expected);
+    }
+}
@@ -0,0 +1,74 @@
+//! Golden-image tests for the software renderer.
+//!
+//! Each test draws a scene and compares it pixel for pixel against a PNG in `tests/golden`.
+//! After an intentional change to how the game looks, regenerate the images with:
+//! `UPDATE_GOLDEN=1 cargo test --test golden`
+
+extern crate png;
+extern crate rusty_pong;
+
+use rusty_pong::render::{self, Renderer};
+use rusty_pong::software_renderer::SoftwareRenderer;
+use rusty_pong::{Simulation, GAME_HEIGHT, GAME_WIDTH, SCORE_TO_WIN};
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+fn game_size() -> (f32, f32) {
+    (GAME_WIDTH as f32, GAME_HEIGHT as f32)
+}
+
+/// Compares the renderer's framebuffer against the named golden image.
+/// On a mismatch, the actual image is written to `target/golden` for inspection.
+fn check_golden(name: &str, renderer: &SoftwareRenderer) {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let path = root.join("tests").join("golden").join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        renderer.save_png(&path).unwrap();
+        return;
+    }
+
+    let decoder = png::Decoder::new(File::open(&path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut expected = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut expected).unwrap();
+    expected.truncate(info.buffer_size());
+
+    let size_matches = (info.width, info.height) == (renderer.width(), renderer.height());
+    if !size_matches || expected != renderer.pixels() {
+        let failure_dir = root.join("target").join("golden");
+        fs::create_dir_all(&failure_dir).unwrap();
+        let actual_path = failure_dir.join(format!("{}.png", name));
+        renderer.save_png(&actual_path).unwrap();
+        panic!("{} does not match its golden image; see {}", name, actual_path.display());
+    }
+}
+
+#[test]
+fn court() {
+    let (width, height) = game_size();
+    let simulation = Simulation::new(width, height);
+    let mut renderer = SoftwareRenderer::new(640, 360, width, height);
+    render::draw_game(&mut renderer, &simulation, 1.0).unwrap_or_else(|e| match e {});
+    check_golden("court", &renderer);
+}
+
+#[test]
+fn court_letterboxed() {
+    let (width, height) = game_size();
+    let simulation = Simulation::new(width, height);
+    let mut renderer = SoftwareRenderer::new(400, 400, width, height);
+    render::draw_game(&mut renderer, &simulation, 1.0).unwrap_or_else(|e| match e {});
+    check_golden("court_letterboxed", &renderer);
+}
+
+#[test]
+fn net_and_score() {
+    let (width, height) = game_size();
+    let mut renderer = SoftwareRenderer::new(1280, 720, width, height);
+    renderer.clear([0.0, 0.0, 0.0, 1.0]).unwrap_or_else(|e| match e {});
+    render::draw_net(&mut renderer, width, height).unwrap_or_else(|e| match e {});
+    render::draw_score(&mut renderer, 7, width * 0.25, 10.0).unwrap_or_else(|e| match e {});
+    render::draw_score(&mut renderer, SCORE_TO_WIN, width * 0.75, 10.0).unwrap_or_else(|e| match e {});
+    check_golden("net_and_score", &renderer);
+}